@@ -0,0 +1,274 @@
+// mlcp - Music Library "Crud" Purge - Copyright (C) 2022, Ian Dunmore
+//
+// Free and open-source software, published under the MIT license; see
+// LICENSE file for more details.
+
+// Verifies music files against the checksum files that come with a rip or
+// download:
+//
+//   .sfv: "name CRC32" lines (";" starts a comment)
+//   .md5: "MD5 name" lines, as written by md5sum (a "*" marks binary mode)
+//   .ffp: "name:MD5" lines, where the MD5 is that of the decoded audio; this
+//         is compared with the MD5 signature in the FLAC file's STREAMINFO
+//
+// Names are relative to the checksum file's folder, and may use "\" as the
+// separator.  Lines that can't be parsed are ignored.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+// File extensions of the checksum files we can verify.
+pub const CHECKSUM_FILE_TYPES: [&str; 3] = [ "ffp", "md5", "sfv"];
+
+// The size of the buffer that files are checksummed through.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+// A file listed in a checksum file that did not verify, and why.
+#[derive(Debug, PartialEq)]
+pub struct Failure {
+    pub file: PathBuf,
+    pub problem: String,
+}
+
+// A checksum, as listed in a checksum file.
+#[derive(Debug, PartialEq)]
+enum Checksum {
+    Crc32(u32),
+    Md5([u8; 16]),
+    // The MD5 of a FLAC file's decoded audio.
+    FlacMd5([u8; 16]),
+}
+
+// Verifies every file listed in a checksum file, returning those that failed.
+// A checksum file that lists nothing is itself a failure, as nothing in its
+// folder has been verified.
+pub fn verify(checksum_file: &Path) -> io::Result<Vec<Failure>> {
+    let text = String::from_utf8_lossy(&fs::read(checksum_file)?).into_owned();
+    let extension = checksum_file.extension().unwrap_or_default().to_string_lossy();
+    let entries = match extension.to_lowercase().as_str() {
+        "sfv" => parse_sfv(&text),
+        "md5" => parse_md5(&text),
+        "ffp" => parse_ffp(&text),
+        _ => Vec::new(),
+    };
+    if entries.is_empty() {
+        return Ok(vec![Failure {
+            file: checksum_file.to_path_buf(),
+            problem: String::from("no checksums found"),
+        }]);
+    }
+
+    let folder = checksum_file.parent().unwrap_or(Path::new(""));
+    let mut failures = Vec::new();
+    for (name, expected) in entries {
+        let file = folder.join(name.replace('\\', "/"));
+        if let Some(problem) = check(&file, &expected) {
+            failures.push(Failure { file, problem });
+        }
+    }
+    Ok(failures)
+}
+
+// Checks a file against its checksum; returning the problem, if it fails.
+fn check(file: &Path, expected: &Checksum) -> Option<String> {
+    if !file.is_file() { return Some(String::from("missing")); }
+    let actual = match expected {
+        Checksum::Crc32(_) => crc32(file).map(Checksum::Crc32),
+        Checksum::Md5(_) => md5(file).map(Checksum::Md5),
+        Checksum::FlacMd5(_) => match flac_md5(file) {
+            Ok(Some(md5)) => Ok(Checksum::FlacMd5(md5)),
+            Ok(None) => return Some(String::from("no MD5 signature in the FLAC file")),
+            Err(error) => Err(error),
+        },
+    };
+    match actual {
+        Ok(actual) if &actual == expected => None,
+        Ok(Checksum::Crc32(_)) => Some(String::from("CRC32 mismatch")),
+        Ok(Checksum::Md5(_)) => Some(String::from("MD5 mismatch")),
+        Ok(Checksum::FlacMd5(_)) => Some(String::from("FLAC audio MD5 mismatch")),
+        Err(error) => Some(error.to_string()),
+    }
+}
+
+// Parses "name CRC32" lines; the name may itself contain spaces.
+fn parse_sfv(text: &str) -> Vec<(String, Checksum)> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(';'))
+        .filter_map(|line| {
+            let (name, crc) = line.rsplit_once(char::is_whitespace)?;
+            let crc = u32::from_str_radix(crc, 16).ok().filter(|_| crc.len() == 8)?;
+            Some((name.trim_end().to_string(), Checksum::Crc32(crc)))
+        })
+        .collect()
+}
+
+// Parses "MD5 name" (or "MD5 *name") lines.
+fn parse_md5(text: &str) -> Vec<(String, Checksum)> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(';') && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (md5, name) = line.split_once(char::is_whitespace)?;
+            let name = name.trim_start();
+            let name = name.strip_prefix('*').unwrap_or(name);
+            Some((name.to_string(), Checksum::Md5(parse_md5_hex(md5)?)))
+        })
+        .collect()
+}
+
+// Parses "name:MD5" lines.
+fn parse_ffp(text: &str) -> Vec<(String, Checksum)> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(';'))
+        .filter_map(|line| {
+            let (name, md5) = line.rsplit_once(':')?;
+            Some((name.to_string(), Checksum::FlacMd5(parse_md5_hex(md5)?)))
+        })
+        .collect()
+}
+
+// An MD5, from its 32 hex digits.
+fn parse_md5_hex(hex: &str) -> Option<[u8; 16]> {
+    if hex.len() != 32 || !hex.is_ascii() { return None; }
+    let mut md5 = [0u8; 16];
+    for (i, byte) in md5.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(md5)
+}
+
+// The CRC32 of a file.
+fn crc32(file: &Path) -> io::Result<u32> {
+    let mut hasher = crc32fast::Hasher::new();
+    read_through(file, |data| hasher.update(data))?;
+    Ok(hasher.finalize())
+}
+
+// The MD5 of a file.
+fn md5(file: &Path) -> io::Result<[u8; 16]> {
+    let mut context = md5::Context::new();
+    read_through(file, |data| context.consume(data))?;
+    Ok(context.compute().0)
+}
+
+// Reads a whole file, a buffer at a time.
+fn read_through(file: &Path, mut consume: impl FnMut(&[u8])) -> io::Result<()> {
+    let mut reader = File::open(file)?;
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    loop {
+        let length = reader.read(&mut buffer)?;
+        if length == 0 { return Ok(()); }
+        consume(&buffer[..length]);
+    }
+}
+
+// The MD5 signature of a FLAC file's decoded audio, from its STREAMINFO
+// block; or None if the encoder didn't set it (it is all zeros).
+fn flac_md5(file: &Path) -> io::Result<Option<[u8; 16]>> {
+    let mut reader = BufReader::new(File::open(file)?);
+    let mut header = [0u8; 10];
+    reader.read_exact(&mut header[..4])?;
+
+    // Skip any ID3 tag in front of the FLAC stream.
+    if &header[..3] == b"ID3" {
+        reader.read_exact(&mut header[4..])?;
+        let mut tag_size = header[6..10].iter().fold(0u64, |size, &b| size << 7 | b as u64);
+        if header[5] & 0x10 != 0 { tag_size += 10; }
+        reader.seek(SeekFrom::Current(tag_size as i64))?;
+        reader.read_exact(&mut header[..4])?;
+    }
+    if &header[..4] != b"fLaC" {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a FLAC file"));
+    }
+
+    // STREAMINFO is always the first block; its MD5 follows 18 bytes of
+    // stream parameters.
+    let mut streaminfo = [0u8; 4 + 34];
+    reader.read_exact(&mut streaminfo)?;
+    if streaminfo[0] & 0x7f != 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "no FLAC STREAMINFO block"));
+    }
+    let mut md5 = [0u8; 16];
+    md5.copy_from_slice(&streaminfo[4 + 18..]);
+    Ok(if md5 == [0u8; 16] { None } else { Some(md5) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A FLAC file's header, with the given MD5 signature in its STREAMINFO.
+    fn flac_header(md5: [u8; 16]) -> Vec<u8> {
+        let mut flac = b"fLaC\x80\0\0\x22".to_vec();
+        flac.extend([0u8; 18]);
+        flac.extend(md5);
+        flac
+    }
+
+    // Creates a folder of files, under "tests/libraries", for verification.
+    fn setup_folder(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let folder = std::env::current_dir().unwrap().join("tests/libraries").join(name);
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        for (name, content) in files {
+            fs::write(folder.join(name), content).unwrap();
+        }
+        folder
+    }
+
+    #[test]
+    fn parse_checksum_lines() {
+        let sfv = parse_sfv("; Generated by foo\r\n01 - Intro.flac 414FA339\r\nbad line\r\n");
+        assert_eq!(sfv, vec![(String::from("01 - Intro.flac"), Checksum::Crc32(0x414fa339))]);
+
+        let md5 = parse_md5("9e107d9d372bb6826bd81d3542a419d6 *CD1\\01.mp3\n");
+        assert_eq!(md5.len(), 1);
+        assert_eq!(md5[0].0, "CD1\\01.mp3");
+
+        let ffp = parse_ffp("01.flac:00112233445566778899aabbccddeeff\n");
+        assert_eq!(ffp[0].0, "01.flac");
+        assert_eq!(ffp[0].1, Checksum::FlacMd5([
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77,
+            0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff,
+        ]));
+    }
+
+    #[test]
+    fn verify_checksum_files() {
+        // "The quick brown fox jumps over the lazy dog" has well-known sums.
+        let fox: &[u8] = b"The quick brown fox jumps over the lazy dog";
+        let signature = [7u8; 16];
+        let folder = setup_folder("checksums", &[
+            ("01.mp3", fox),
+            ("02.mp3", b"corrupted"),
+            ("01.flac", &flac_header(signature)),
+            ("02.flac", &flac_header([0u8; 16])),
+            ("good.sfv", b"01.mp3 414FA339\n"),
+            ("bad.sfv", b"01.mp3 414FA339\n02.mp3 414FA339\n03.mp3 414FA339\n"),
+            ("good.md5", b"9e107d9d372bb6826bd81d3542a419d6 *01.mp3\n"),
+            ("bad.md5", b"9e107d9d372bb6826bd81d3542a419d6  02.mp3\n"),
+            ("good.ffp", b"01.flac:07070707070707070707070707070707\n"),
+            ("bad.ffp", b"01.flac:00000000000000000000000000000000\n\
+                          02.flac:07070707070707070707070707070707\n"),
+            ("empty.sfv", b"; nothing here\n"),
+        ]);
+
+        for good in ["good.sfv", "good.md5", "good.ffp"] {
+            assert_eq!(verify(&folder.join(good)).unwrap(), Vec::new(), "{}", good);
+        }
+
+        let problems = |name: &str| -> Vec<String> {
+            verify(&folder.join(name)).unwrap().into_iter().map(|f| f.problem).collect()
+        };
+        assert_eq!(problems("bad.sfv"), vec!["CRC32 mismatch", "missing"]);
+        assert_eq!(problems("bad.md5"), vec!["MD5 mismatch"]);
+        assert_eq!(
+            problems("bad.ffp"),
+            vec!["FLAC audio MD5 mismatch", "no MD5 signature in the FLAC file"]
+        );
+        assert_eq!(problems("empty.sfv"), vec!["no checksums found"]);
+    }
+}
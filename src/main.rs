@@ -3,46 +3,126 @@
 // Free and open-source software, published under the MIT license; see
 // LICENSE file for more details.
 
-use glob::glob;
+mod checksums;
+mod playlists;
+mod rules;
+mod tags;
+
+use glob::{glob, MatchOptions, Pattern};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
 use std::process::exit;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
 use std::ffi::OsStr;
 
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use indicatif::{ProgressBar, ProgressStyle};
 use console::style;
-use clap::{Parser};
+use clap::{Parser, Subcommand, ValueEnum};
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use rules::{FileFacts, Rule, RuleAction};
 
 // Significant File Types ...
 
 // File extensions typically associated with music/album files.
-const MUSIC_FILE_TYPES: [&str; 17] = [
+const MUSIC_FILE_TYPES: [&str; 16] = [
     "aac", "aiff", "ape", "dff", "dsd", "dsf", "dxd", "flac", "iso", "m4a",
-    "m4p", "mp3", "oga", "ogg", "wav", "wma", "wmv"
+    "m4p", "mp3", "oga", "ogg", "wav", "wma"
 ];
 
 // File extensions typically associated with non-music audio files.
-const AUDIO_FILE_TYPES: [&str; 28] = [
+const AUDIO_FILE_TYPES: [&str; 27] = [
     "3gp", "aa", "aax", "act", "amr", "au", "awb", "dct", "dss", "dvf", "gsm", "iklax", "ivs",
-    "m4b","mmf", "mpc","msv","mogg", "opus","ra","rm","raw","sln","tta", "vox","wmv","wv","webm"
+    "m4b","mmf", "mpc","msv","mogg", "opus","ra","rm","raw","sln","tta", "vox","wv","webm"
+];
+
+// File extensions of video files (e.g. music videos and concert footage).
+const VIDEO_FILE_TYPES: [&str; 11] = [
+    "avi", "flv", "m2ts", "m4v", "mkv", "mov", "mp4", "mpeg", "mpg", "vob", "wmv"
 ];
 
 // Common document/booklet file extensions.
 const DOCUMENT_FILE_TYPES: [&str; 2] = [ "txt", "pdf"];
 
-// Alburm Art Filenames (Folder Level)
-const ALBUM_ART_FILENAMES: [&str; 9] = ["album", "cover", "small_cover", "large_cover", "folder",
-    "thumb", "albumartsmall", "albumartmedium", "albumartlarge"
+// File extensions of rip metadata; cue sheets, rip logs and checksums, which
+// record the provenance of a rip.
+const RIP_METADATA_FILE_TYPES: [&str; 6] = [ "accurip", "cue", "ffp", "log", "md5", "sfv"];
+
+// File extensions of playlists; the files they reference are never purged.
+const PLAYLIST_FILE_TYPES: [&str; 4] = [ "m3u", "m3u8", "pls", "xspf"];
+// Playlist file extensions that rippers also write as a record of the rip, so
+// are kept along with the rip metadata (as well as with the playlists).
+const RIP_PLAYLIST_FILE_TYPES: [&str; 1] = [ "m3u8"];
+
+// File extensions of sidecar files (synced lyrics, and tag or scene
+// metadata), which belong to the music file with the same name.
+const SIDECAR_FILE_TYPES: [&str; 3] = [ "json", "lrc", "nfo"];
+
+// File extensions, and name prefixes, of partial (in progress, or abandoned)
+// downloads and temporary files, left by browsers and download clients.
+const PARTIAL_DOWNLOAD_TYPES: [&str; 8] = [
+    "!qb", "!ut", "aria2", "crdownload", "opdownload", "part", "partial", "tmp"
+];
+const PARTIAL_DOWNLOAD_PREFIXES: [&str; 1] = ["~$"];
+// Partial downloads modified within this many minutes are left alone, as the
+// download may still be in progress.
+const DEFAULT_DOWNLOAD_GRACE_MINUTES: u64 = 30;
+
+// File names (matched ignoring case) of "system junk"; metadata and thumbnail
+// caches left by operating systems and applications (e.g. Finder, Explorer,
+// Picasa).  Always purged, unless kept with --keep-junk.
+const SYSTEM_JUNK_FILES: [&str; 8] = [
+    ".DS_Store", ".directory", ".localized", ".picasa.ini", "desktop.ini", "ehthumbs.db",
+    "picasa.ini", "Thumbs.db"
+];
+
+// Album Art Name Patterns (Folder Level); case-insensitive regexes matched
+// against the file name without its extension.
+const ALBUM_ART_PATTERNS: [&str; 5] = [
+    // Common names: "cover.jpg", "Folder.jpg", "front.png" ...
+    r"^(album|cover|folder|front|thumb)$",
+    r"^(small|large)_cover$",
+    // ... qualified names from rippers: "Cover (Front).jpg", "folder [1].png" ...
+    r"^(album|cover|folder|front)\s*(\(.*\)|\[.*\])$",
+    // ... and Windows Media Player: "AlbumArtSmall.jpg", "AlbumArt_{GUID}_Large.jpg".
+    r"^albumart(small|medium|large)?$",
+    r"^albumart_\{[0-9a-f-]+\}_(small|large)$",
 ];
 
 // Album Art Extensions (Folder Level)
-const ALBUM_ART_EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+const ALBUM_ART_EXTENSIONS: [&str; 10] = [
+    "avif", "bmp", "gif", "jpeg", "jpg", "jxl", "png", "tif", "tiff", "webp"
+];
+
+// Album Art and Folder Naming
+// The art names preferred by "--art-mode one", best first.
+const ART_NAME_PRIORITY: [&str; 4] = ["cover", "folder", "front", "albumartlarge"];
+// Disc subfolders of an album (e.g. "CD1", "Disc 2", "disk_03").
+const DISC_FOLDER_PATTERN: &str = r"^(cd|disc|disk)\s*[-_.]?\s*\d+\b";
+// The default name of art extracted by "art extract" (without an extension).
+const EXTRACTED_ART_NAME: &str = "folder";
 
 // Operation Indicators
 const PURGE: &str = "PURGED";
 const BACKUP: &str = "BACKED-UP";
 const SIMULATE: &str = "SIMULATED";
 const ERROR: &str = "ERROR ";
+const PROTECTED: &str = "PROTECTED";
+const NO_MUSIC: &str = "NO MUSIC";
+const VERIFIED: &str = "VERIFIED";
+const RENAME: &str = "RENAMED";
+const COPY: &str = "COPIED";
+const EXTRACT: &str = "EXTRACTED";
+const WARNING: &str = "WARNING";
+const POLICY: &str = "POLICY";
 
 // Path and File
 const WILDCARD: &str = "**/*.*";
@@ -51,13 +131,49 @@ const NO_EXTENSION: &str = "";
 const NO_FILE_NAME: &str = "";
 const NO_CHAR: char = ' ';
 
+// Include/exclude patterns: "*" stays within a folder, "**" spans folders.
+const PATH_MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+// Per-folder ignore file (gitignore syntax), protecting matching files.
+const IGNORE_FILE_NAME: &str = ".mlcpignore";
+// Per-folder configuration file (TOML), overriding category policies.
+const CONFIG_FILE_NAME: &str = ".mlcp.toml";
+
 // Resource fork characters 1 & 2 (i.e. "._")
 const RES_FORK_1: char = '.';
 const RES_FORK_2: char = '_';
+// The header that starts every AppleDouble file.
+const APPLE_DOUBLE_MAGIC: [u8; 4] = [0x00, 0x05, 0x16, 0x07];
+
+// Size Units (Binary)
+const SIZE_UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+const SIZE_UNIT_STEP: f64 = 1024.0;
+
+// Inventory label for files without an extension.
+const NO_EXTENSION_LABEL: &str = "(none)";
 
-// Exit Codes
+// Exit Codes (see EXIT_CODES_HELP); error counts are reported, not returned.
 const SUCCESS: i32 = 0;
 const PATH_DOES_NOT_EXIST: i32 = 1;
+const INVALID_ARGUMENTS: i32 = 2;
+const NOTHING_TO_DO: i32 = 3;
+const PARTIAL_FAILURE: i32 = 4;
+const TOTAL_FAILURE: i32 = 5;
+const SAFETY_ABORT: i32 = 6;
+
+const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  Success; all crud files were purged, backed-up or simulated
+  1  LIBRARY_PATH or BACKUP_PATH does not exist
+  2  Invalid arguments
+  3  Nothing to do; no crud files were found
+  4  Partial failure; some files could not be purged or backed-up
+  5  Total failure; no files could be purged or backed-up
+  6  Safety abort; the requested operation could damage the library";
 
 #[derive(Parser, Debug)]
 /// Music Library Crud Purge - Purge, or backup, "crud" files from a specified music library.
@@ -69,14 +185,18 @@ const PATH_DOES_NOT_EXIST: i32 = 1;
 /// Unless the --purge option is specified, NO changes to the library will occur!
 /// This allows simulation of the purge/backup process to see what files will
 /// be affected.
-#[clap(author, version, about)]
+#[clap(author, version, about, after_long_help=EXIT_CODES_HELP)]
+#[clap(args_conflicts_with_subcommands=true, subcommand_negates_reqs=true)]
 struct Args {    
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// Root folder for the music library to be purged
     /// 
     /// All sub-folders will be processed recursively; specifying the root of
     /// the library will process all files in the library.  You can process a 
     /// single artist or album by specifying its respective path.
-    #[clap(required=true, conflicts_with="list-types")]
+    #[clap(required=true, conflicts_with="list_types")]
     library_path: Option<String>,
     
     /// Root folder for backing up purged files
@@ -84,7 +204,7 @@ struct Args {
     /// If [BACKUP_PATH] is specified, files are moved here instead of deleted.
     /// The original folder structure is preserved, so they can be merged back
     /// into the library simply by copying the backup root to the library root.
-    #[clap(conflicts_with="list-types")]
+    #[clap(conflicts_with="list_types")]
     backup_path: Option<String>,
 
     /// Perform the actual file purge
@@ -93,32 +213,218 @@ struct Args {
     /// operation.  Otherwise NO changes occur, and the process is just
     /// simulated so the affects can be evaluated (with -v | --verbose)
     /// prior to making them permanent.  
-    #[clap(short, long, conflicts_with="list-types")]
+    #[clap(short, long, conflicts_with="list_types")]
     purge: bool,
 
     /// Purge folder-level album art.
     /// 
     /// Causes folder-level album art to be purged; useful if space is at a
     /// premium (or when all files have embedded art and the folder-level files
-    /// are holdovers from a download.
-    #[clap(short, long, conflicts_with="list-types")]
+    /// are holdovers from a download).  The same as "--art-mode purge".
+    #[clap(short, long, conflicts_with="list_types")]
     art: bool,
 
+    /// How folder-level album art is treated
+    ///
+    /// "keep" (the default) keeps all art, and "purge" purges it (as -a).
+    /// "one" keeps only the best art file in each album (see --art-priority),
+    /// and purges the rest.  "if-embedded" purges an album's art only if all
+    /// of its music files have embedded art (in ID3, FLAC, MP4 or Ogg tags).
+    #[clap(
+        long, value_name="MODE", value_enum, conflicts_with_all=["art", "list_types"]
+    )]
+    art_mode: Option<ArtMode>,
+
+    /// Art names to prefer for "--art-mode one", best first (comma separated)
+    ///
+    /// Names are compared, ignoring case, with the file name without its
+    /// extension.  Art files of equal priority are ranked by resolution,
+    /// then file size; the largest is kept.
+    #[clap(
+        long, value_name="NAMES", value_delimiter=',',
+        default_values_t=ART_NAME_PRIORITY.map(String::from), conflicts_with="list_types"
+    )]
+    art_priority: Vec<String>,
+
+    /// Rename each folder's kept art to this name (e.g. cover.jpg)
+    ///
+    /// The art kept by "--art-mode one", or the best ranked art in folders that
+    /// keep all of their art, is renamed; as some players only show art with
+    /// a specific name.  Without an extension (e.g. "cover"), the art keeps
+    /// its own.  Art is never converted, so art in a different format to the
+    /// name's extension is left alone, with a warning.
+    #[clap(long, value_name="NAME", conflicts_with="list_types")]
+    art_name: Option<String>,
+
+    /// Copy the art to the --art-name, rather than renaming it
+    #[clap(long, requires="art_name", conflicts_with="list_types")]
+    copy_art: bool,
+
+    /// Also recognize art by this name pattern (repeatable)
+    ///
+    /// A case-insensitive regex matched against the file name without its
+    /// extension (e.g. "^scan\d+$"), for files with an album art extension
+    /// (see --list-types).  Adds to the default patterns, which cover common
+    /// names (cover, folder, front, "Cover (Front)") and Windows Media
+    /// Player's "AlbumArt_{GUID}_Large" naming.
+    #[clap(long, value_name="REGEX", value_parser=parse_art_pattern, global=true)]
+    art_pattern: Vec<Regex>,
+
+    /// Also recognize art by this file name glob (repeatable)
+    ///
+    /// A case-insensitive glob matched against the whole file name, with any
+    /// extension (e.g. "*_cover.heic").
+    #[clap(long, value_name="GLOB", global=true)]
+    art_glob: Vec<Pattern>,
+
+    /// Also recognize art with this extension (repeatable)
+    ///
+    /// Adds to the default album art extensions (see --list-types), for
+    /// files whose names match an art pattern.
+    #[clap(long, value_name="EXT", global=true)]
+    art_ext: Vec<String>,
+
     /// Keep other (non-music) audio files
     /// 
     /// "Other" audio files are any audio file type that is not commonly used
     /// to store music.  By default, such files are DELETED (or backed up, if
     /// the -b | --backup flag is specified).
-    #[clap(short, long, conflicts_with="list-types")]
+    #[clap(short, long, conflicts_with="list_types")]
     other_audio: bool,
 
     /// Keep document/booklet files (e.g. .txt, .pdf).
     ///
     /// Document/booklet files are often found in digital downloads, and are
     /// purged by default.  This option keeps those files intact.
-    #[clap(short, long, conflicts_with="list-types")]
+    #[clap(short, long, conflicts_with="list_types")]
     documents: bool,
 
+    /// Keep rip metadata files (e.g. .cue, .log, .accurip)
+    ///
+    /// Cue sheets, rip logs and checksums record the provenance of a rip;
+    /// essential for an archive, but not for playback.  They are purged by
+    /// default; this option keeps them, separately from --documents.
+    #[clap(long, conflicts_with="list_types")]
+    keep_rip_metadata: bool,
+
+    /// Keep video files (e.g. .mp4, .mkv)
+    ///
+    /// Music videos and concert footage are purged by default; this option
+    /// keeps them.
+    #[clap(long, conflicts_with="list_types")]
+    keep_video: bool,
+
+    /// Keep playlist files (e.g. .m3u, .pls)
+    ///
+    /// Playlists are purged by default; this option keeps them.  Either way,
+    /// the files that playlists in the library reference are never purged,
+    /// and any that are missing are reported.
+    #[clap(long, conflicts_with="list_types")]
+    keep_playlists: bool,
+
+    /// Keep system junk files (e.g. .DS_Store, Thumbs.db)
+    ///
+    /// System junk files are metadata and thumbnail caches left by operating
+    /// systems and applications (see --list-types).  They are always purged
+    /// by default, whichever other file types are kept.
+    #[clap(long, conflicts_with="list_types")]
+    keep_junk: bool,
+
+    /// Spare partial downloads modified within this many minutes
+    ///
+    /// Partial downloads and temporary files (e.g. .part, .crdownload, see
+    /// --list-types) are purged; but not while they may still be being
+    /// downloaded.  0 purges them regardless.
+    #[clap(
+        long, value_name="MINUTES", default_value_t=DEFAULT_DOWNLOAD_GRACE_MINUTES,
+        conflicts_with="list_types"
+    )]
+    download_grace: u64,
+
+    /// Only process paths matching this glob pattern (repeatable)
+    ///
+    /// Patterns are matched against each file's path relative to
+    /// LIBRARY_PATH; "*" matches within a folder and "**" spans folders
+    /// (e.g. "*/Soundtracks/**").  If any are given, only files matching at
+    /// least one --include pattern are considered for purging.
+    #[clap(long, value_name="PATTERN", conflicts_with="list_types")]
+    include: Vec<Pattern>,
+
+    /// Skip paths matching this glob pattern (repeatable)
+    ///
+    /// Patterns are matched as for --include (e.g. "Classical/**"); files
+    /// matching any --exclude pattern are never purged.
+    #[clap(long, value_name="PATTERN", conflicts_with="list_types")]
+    exclude: Vec<Pattern>,
+
+    /// Purge or keep files matching a rule expression (repeatable)
+    ///
+    /// Rules take the form "purge if CONDITION" or "keep if CONDITION", and
+    /// override the file type options; e.g. "purge if ext in [jpg,png] and
+    /// size > 2MB and not name ~ \"^(cover|folder)\"".  Conditions test ext,
+    /// name, path (relative to LIBRARY_PATH), size (e.g. 2MB) and age (of
+    /// the modification time, e.g. 30d), and combine with and, or, not and
+    /// parentheses.  Rules in .mlcp.toml files are checked first, closest
+    /// folder first, then these; the first matching rule decides.
+    #[clap(long, value_name="RULE", conflicts_with="list_types")]
+    rule: Vec<Rule>,
+
+    /// Purge folder-level album art larger than this size (e.g. 2MB)
+    ///
+    /// Useful for dropping large scans (e.g. 30MB TIFFs from vinyl rips),
+    /// while keeping normal-sized art.  The same as "--max-size art=SIZE".
+    #[clap(long, value_name="SIZE", value_parser=rules::parse_size, conflicts_with="list_types")]
+    max_art_size: Option<u64>,
+
+    /// Purge kept files of a category smaller than a size (repeatable)
+    ///
+    /// Takes the form CATEGORY=SIZE, where CATEGORY is one of music, audio,
+    /// document, rip-metadata, sidecar, video, playlist or art, and SIZE is
+    /// e.g. 500KB; e.g. "art=10KB" purges tiny thumbnails.  Only files that
+    /// would otherwise be kept are affected.
+    #[clap(long, value_name="CATEGORY=SIZE", conflicts_with="list_types")]
+    min_size: Vec<SizeLimit>,
+
+    /// Purge kept files of a category larger than a size (repeatable)
+    ///
+    /// Takes the same CATEGORY=SIZE form as --min-size; e.g. "document=20MB"
+    /// purges large scanned booklets, even when documents are kept.
+    #[clap(long, value_name="CATEGORY=SIZE", conflicts_with="list_types")]
+    max_size: Vec<SizeLimit>,
+
+    /// Only purge files last modified before this age or date
+    ///
+    /// Takes a duration (e.g. 30d, 12h, 2w) or a UTC date (e.g. 2024-01-31
+    /// or 2024-01-31T18:00).  Gives newly added files a grace period, so a
+    /// fresh download's booklet can still be read before it is purged.
+    #[clap(long, value_name="AGE|DATE", conflicts_with="list_types")]
+    older_than: Option<TimeLimit>,
+
+    /// Only purge files last modified after this age or date
+    ///
+    /// Takes the same durations and dates as --older-than; e.g. the date of
+    /// the last run, to only purge files added since.
+    #[clap(long, value_name="AGE|DATE", conflicts_with="list_types")]
+    newer_than: Option<TimeLimit>,
+
+    /// Purge all AppleDouble ("._") files
+    ///
+    /// macOS writes an AppleDouble file (e.g. "._song.mp3") alongside each
+    /// file it copies to a non-macOS volume (e.g. an exFAT or FAT32 DAP card).
+    /// They are only needed by macOS, so can be purged from such volumes.  By
+    /// default, only those whose file is missing, or being purged, are purged.
+    #[clap(long, conflicts_with="list_types")]
+    purge_apple_double: bool,
+
+    /// Verify music against .sfv, .md5 and .ffp files before purging them
+    ///
+    /// Each file listed in a folder's checksum files is checked; failures are
+    /// reported, and the checksum files of a folder that fails are kept.
+    /// Only those of folders that verify cleanly are purged.  This reads all
+    /// of the listed music, so can take some time.
+    #[clap(long, conflicts_with="list_types")]
+    verify_checksums: bool,
+
     /// List "music" vs. "audio" file types
     /// 
     /// Lists both the "Music" files types, which are NEVER purged (green), as
@@ -131,15 +437,490 @@ struct Args {
     /// 
     /// Outputs the full path of every file or folder that is touched,
     /// along with the operation performed on it: PURGED (deleted), MOVED
-    /// (backed-up), DIR (directory;not touched), RES (resource, skipped).
-    #[clap(short, long, conflicts_with="list-types")]
+    /// (backed-up), DIR (directory;not touched), RES (resource, skipped),
+    /// PROTECTED (crud kept by a .mlcpignore file or rule, with the reason),
+    /// NO MUSIC (a folder with no music anywhere in it).
+    #[clap(short, long, conflicts_with="list_types")]
     verbose: bool,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Inventory the file types (extensions) found in a music library
+    ///
+    /// Lists every extension in the library, with its file count, total size
+    /// and classification: music, other audio, document, art or unknown.
+    /// Unknown extensions are always purged, so this shows which file types
+    /// would be lost before the type lists are extended to cover them.
+    Inventory {
+        /// Root folder for the music library to be inventoried
+        library_path: String,
+    },
+
+    /// Manage folder-level album art
+    Art {
+        #[clap(subcommand)]
+        command: ArtCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ArtCommand {
+    /// Write folder art from the art embedded in each folder's music
+    ///
+    /// For each folder with music but no folder-level album art, writes the
+    /// front cover embedded in the first track (by name) that has one.  Art
+    /// is recognized as for purging, including by the --art-pattern,
+    /// --art-glob and --art-ext options.  The file type (e.g. .jpg or .png)
+    /// is taken from the image data.  Unless the --purge option is
+    /// specified (as for purging), the extraction is only simulated.
+    Extract {
+        /// Root folder for the music library to extract art in
+        library_path: String,
+
+        /// The name for the extracted art, without its extension
+        ///
+        /// An album art extension given (e.g. "folder.jpg") is replaced by
+        /// that of the image.  The name should be recognized as album art (see the
+        /// --art-pattern option), or the art would be purged as crud.
+        #[clap(long, default_value=EXTRACTED_ART_NAME)]
+        name: String,
+
+        /// Perform the actual extraction, writing the art files
+        #[clap(short, long)]
+        purge: bool,
+
+        /// Enables verbose output
+        #[clap(short, long)]
+        verbose: bool,
+    },
+}
+
+// File categories, as used to classify extensions found in the library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileCategory {
+    Music,
+    Audio,
+    Document,
+    RipMetadata,
+    Sidecar,
+    Video,
+    Playlist,
+    Art,
+    Unknown,
+}
+
+impl FileCategory {
+    // Display label for the category.
+    fn label(&self) -> &'static str {
+        match self {
+            FileCategory::Music => "music",
+            FileCategory::Audio => "other audio",
+            FileCategory::Document => "document",
+            FileCategory::RipMetadata => "rip metadata",
+            FileCategory::Sidecar => "sidecar",
+            FileCategory::Video => "video",
+            FileCategory::Playlist => "playlist",
+            FileCategory::Art => "art",
+            FileCategory::Unknown => "unknown",
+        }
+    }
+
+    // The (command line) name of the category; the inverse of from_name.
+    fn name(&self) -> &'static str {
+        match self {
+            FileCategory::Music => "music",
+            FileCategory::Audio => "audio",
+            FileCategory::Document => "document",
+            FileCategory::RipMetadata => "rip-metadata",
+            FileCategory::Sidecar => "sidecar",
+            FileCategory::Video => "video",
+            FileCategory::Playlist => "playlist",
+            FileCategory::Art => "art",
+            FileCategory::Unknown => "unknown",
+        }
+    }
+
+    // The category with the given (command line) name, if any.
+    fn from_name(name: &str) -> Option<FileCategory> {
+        match name.to_lowercase().as_str() {
+            "music" => Some(FileCategory::Music),
+            "audio" => Some(FileCategory::Audio),
+            "document" | "documents" => Some(FileCategory::Document),
+            "rip-metadata" => Some(FileCategory::RipMetadata),
+            "sidecar" | "sidecars" => Some(FileCategory::Sidecar),
+            "video" | "videos" => Some(FileCategory::Video),
+            "playlist" | "playlists" => Some(FileCategory::Playlist),
+            "art" => Some(FileCategory::Art),
+            _ => None,
+        }
+    }
+}
+
+// A size limit for one category of file, in the form CATEGORY=SIZE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SizeLimit {
+    category: FileCategory,
+    size: u64,
+}
+
+impl FromStr for SizeLimit {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (name, size) = text.split_once('=')
+            .ok_or(format!("expected CATEGORY=SIZE (e.g. art=2MB), found \"{}\"", text))?;
+        let category = FileCategory::from_name(name.trim()).ok_or(format!(
+            "unknown category \"{}\" (expected music, audio, document, rip-metadata, \
+             sidecar, video, playlist or art)", name
+        ))?;
+        Ok(SizeLimit { category, size: rules::parse_size(size)? })
+    }
+}
+
+impl fmt::Display for SizeLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // As it would be given on the command line, so it can be reused.
+        write!(f, "{}={}", self.category.name(), format_exact_size(self.size))
+    }
+}
+
+// A point in time, from an age relative to now (e.g. "30d") or a date.
+#[derive(Debug, Clone)]
+struct TimeLimit {
+    text: String,
+    time: SystemTime,
+}
+
+impl FromStr for TimeLimit {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        // Dates contain "-" (e.g. 2024-01-31); anything else is an age.
+        let time = if text.contains('-') {
+            rules::parse_date(text)?
+        } else {
+            let age = rules::parse_duration(text)?;
+            SystemTime::now().checked_sub(age).unwrap_or(SystemTime::UNIX_EPOCH)
+        };
+        Ok(TimeLimit { text: String::from(text), time })
+    }
+}
+
+// Recognizes folder-level album art by name pattern and extension, or glob.
+#[derive(Debug, Clone)]
+struct ArtMatcher {
+    patterns: Vec<Regex>,
+    globs: Vec<Pattern>,
+    extensions: Vec<String>,
+}
+
+impl ArtMatcher {
+    // The default art patterns and extensions, plus any additional ones.
+    fn new(patterns: Vec<Regex>, globs: Vec<Pattern>, extensions: Vec<String>) -> ArtMatcher {
+        let default_patterns = ALBUM_ART_PATTERNS.iter()
+            .map(|p| parse_art_pattern(p).expect("Invalid default art pattern."));
+        let default_extensions = ALBUM_ART_EXTENSIONS.iter().map(|e| String::from(*e));
+        ArtMatcher {
+            patterns: default_patterns.chain(patterns).collect(),
+            globs,
+            extensions: default_extensions
+                .chain(extensions.iter().map(|e| e.trim_start_matches('.').to_lowercase()))
+                .collect(),
+        }
+    }
+
+    // Determines whether a file name is that of folder-level album art.
+    fn is_art(&self, file_name: &str) -> bool {
+        let glob_options = MatchOptions { case_sensitive: false, ..PATH_MATCH_OPTIONS };
+        if self.globs.iter().any(|g| g.matches_with(file_name, glob_options)) { return true; }
+
+        let path = Path::new(file_name);
+        let extension = opt_osstr_to_string(path.extension(), NO_EXTENSION).to_lowercase();
+        if !self.extensions.contains(&extension) { return false; }
+        let stem = opt_osstr_to_string(path.file_stem(), NO_FILE_NAME);
+        self.patterns.iter().any(|p| p.is_match(&stem))
+    }
+}
+
+impl Default for ArtMatcher {
+    fn default() -> Self {
+        ArtMatcher::new(Vec::new(), Vec::new(), Vec::new())
+    }
+}
+
+// Count and total size of the files found for a single extension.
+#[derive(Debug, Default)]
+struct InventoryEntry {
+    file_count: u64,
+    total_size: u64,
+}
+
+// Errors from purging, or backing up, a single file; each keeps the
+// underlying OS error so the reason for the failure can be reported.
+#[derive(Debug)]
+enum PurgeError {
+    // The backup target directory could not be created.
+    CreateDir { path: PathBuf, source: io::Error },
+    // The file could not be copied to the backup target.
+    Backup { path: PathBuf, target: PathBuf, source: io::Error },
+    // The file could not be removed from the library.
+    Purge { path: PathBuf, source: io::Error },
+    // The file could not be renamed (or copied) to its new name.
+    Rename { path: PathBuf, target: PathBuf, source: io::Error },
+    // A music file's embedded art could not be written to the target.
+    Extract { path: PathBuf, target: PathBuf, source: io::Error },
+}
+
+impl PurgeError {
+    // The kind of OS error behind this failure (e.g. permission denied).
+    fn kind(&self) -> io::ErrorKind {
+        self.io_error().kind()
+    }
+
+    fn io_error(&self) -> &io::Error {
+        match self {
+            PurgeError::CreateDir { source, .. } => source,
+            PurgeError::Backup { source, .. } => source,
+            PurgeError::Purge { source, .. } => source,
+            PurgeError::Rename { source, .. } => source,
+            PurgeError::Extract { source, .. } => source,
+        }
+    }
+}
+
+impl fmt::Display for PurgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PurgeError::CreateDir { path, source } => write!(
+                f, "Could not create target directory: {} ({})", path.display(), source
+            ),
+            PurgeError::Backup { path, target, source } => write!(
+                f, "Could not backup: {} -> {} ({})", path.display(), target.display(), source
+            ),
+            PurgeError::Purge { path, source } => write!(
+                f, "Could not purge: {} ({})", path.display(), source
+            ),
+            PurgeError::Rename { path, target, source } => write!(
+                f, "Could not rename: {} -> {} ({})", path.display(), target.display(), source
+            ),
+            PurgeError::Extract { path, target, source } => write!(
+                f, "Could not extract art: {} -> {} ({})", path.display(), target.display(), source
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PurgeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.io_error())
+    }
+}
+
+// The outcome of scanning the library: the files to be purged, and the
+// files that would have been purged but are protected (and why).
+#[derive(Debug, Default)]
+struct PurgePlan {
+    purge_files: Vec<PathBuf>,
+    protected_files: Vec<ProtectedFile>,
+    // Why a file is being purged, where it is not simply its file type.
+    purge_reasons: HashMap<PathBuf, String>,
+    // Art to be renamed (or copied) to the canonical art name.
+    art_renames: Vec<ArtRename>,
+    // Problems that don't stop the run, e.g. art that can't be renamed.
+    warnings: Vec<String>,
+    // The top-most folders with no music anywhere in them.
+    no_music_folders: Vec<PathBuf>,
+    // Folders whose music verified against all of their checksum files.
+    verified_folders: Vec<PathBuf>,
+}
+
+// Folder art to be written from the art embedded in a music file.
+#[derive(Debug, PartialEq)]
+struct ArtExtraction {
+    source: PathBuf,
+    target: PathBuf,
+}
+
+// A folder's chosen art, and the canonical name it is renamed (or copied) to.
+#[derive(Debug, PartialEq)]
+struct ArtRename {
+    from: PathBuf,
+    to: PathBuf,
+}
+
+// A file protected from purging, with the reason it was protected.
+#[derive(Debug)]
+struct ProtectedFile {
+    path: PathBuf,
+    reason: String,
+}
+
+// How folder-level album art is treated.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum ArtMode {
+    // Keep all of the art in a folder.
+    #[default]
+    Keep,
+    // Purge all of the art in a folder.
+    Purge,
+    // Keep only the best art file in a folder, and purge the rest.
+    One,
+    // Purge the art in a folder only if all of its music embeds art.
+    IfEmbedded,
+}
+
+impl fmt::Display for ArtMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArtMode::Keep => write!(f, "keep"),
+            ArtMode::Purge => write!(f, "purge"),
+            ArtMode::One => write!(f, "one"),
+            ArtMode::IfEmbedded => write!(f, "if-embedded"),
+        }
+    }
+}
+
+// The "art" key of a ".mlcp.toml" file; either a mode (e.g. "one"), or (as
+// for the original flag) true to purge art and false to keep it.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(untagged)]
+enum ArtSetting {
+    Flag(bool),
+    Mode(ArtMode),
+}
+
+impl From<ArtSetting> for ArtMode {
+    fn from(setting: ArtSetting) -> Self {
+        match setting {
+            ArtSetting::Flag(true) => ArtMode::Purge,
+            ArtSetting::Flag(false) => ArtMode::Keep,
+            ArtSetting::Mode(mode) => mode,
+        }
+    }
+}
+
+// Which categories of file are kept, and how art is treated; set from the
+// command line, and overridden per-folder by ".mlcp.toml" files.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+struct CategoryPolicy {
+    art: ArtMode,
+    keep_other_audio: bool,
+    keep_documents: bool,
+    keep_rip_metadata: bool,
+    keep_video: bool,
+    keep_playlists: bool,
+    keep_junk: bool,
+}
+
+impl fmt::Display for CategoryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = |keep: bool| if keep { "keep" } else { "purge" };
+        write!(
+            f,
+            "art: {}, other audio: {}, documents: {}, rip metadata: {}, video: {}, playlists: {}, \
+             junk: {}",
+            self.art, action(self.keep_other_audio), action(self.keep_documents),
+            action(self.keep_rip_metadata), action(self.keep_video), action(self.keep_playlists),
+            action(self.keep_junk)
+        )
+    }
+}
+
+// The contents of a ".mlcp.toml" file.  Keys mirror the command line flags
+// (e.g. "other_audio = true" keeps other audio); omitted keys are inherited.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FolderConfig {
+    art: Option<ArtSetting>,
+    other_audio: Option<bool>,
+    documents: Option<bool>,
+    rip_metadata: Option<bool>,
+    video: Option<bool>,
+    playlists: Option<bool>,
+    junk: Option<bool>,
+    // Rules for this folder (and below), checked in order.
+    #[serde(default)]
+    rules: Vec<Rule>,
+}
+
+impl FolderConfig {
+    // Applies this folder's overrides on top of an inherited policy.
+    fn apply(&self, policy: CategoryPolicy) -> CategoryPolicy {
+        CategoryPolicy {
+            art: self.art.map_or(policy.art, ArtMode::from),
+            keep_other_audio: self.other_audio.unwrap_or(policy.keep_other_audio),
+            keep_documents: self.documents.unwrap_or(policy.keep_documents),
+            keep_rip_metadata: self.rip_metadata.unwrap_or(policy.keep_rip_metadata),
+            keep_video: self.video.unwrap_or(policy.keep_video),
+            keep_playlists: self.playlists.unwrap_or(policy.keep_playlists),
+            keep_junk: self.junk.unwrap_or(policy.keep_junk),
+        }
+    }
+
+    // Determines whether this folder overrides any of the policy (rather than
+    // just adding rules).
+    fn overrides_policy(&self) -> bool {
+        self.art.is_some() || self.other_audio.is_some() || self.documents.is_some()
+            || self.rip_metadata.is_some() || self.video.is_some()
+            || self.playlists.is_some() || self.junk.is_some()
+    }
+}
+
+// Options controlling which files in the library are purged.
+#[derive(Debug, Default)]
+struct PurgeOptions {
+    // Policy from the command line, and any per-folder overrides of it.
+    policy: CategoryPolicy,
+    folder_configs: BTreeMap<PathBuf, FolderConfig>,
+    // Include/exclude patterns are relative to the library root.
+    library_root: PathBuf,
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    // Recognizes the folder-level art that is kept (unless purging art).
+    art: ArtMatcher,
+    // Art names preferred by "--art-mode one", best first (lower-case).
+    art_priority: Vec<String>,
+    // The canonical name each folder's chosen art is renamed, or copied, to.
+    art_name: Option<String>,
+    copy_art: bool,
+    // Rules from the command line.
+    rules: Vec<Rule>,
+    // Size limits, beyond which files that would be kept are purged.
+    min_sizes: Vec<SizeLimit>,
+    max_sizes: Vec<SizeLimit>,
+    // Modification time window, outside of which nothing is purged.
+    older_than: Option<TimeLimit>,
+    newer_than: Option<TimeLimit>,
+    // Purge all AppleDouble files, not just orphaned ones.
+    purge_apple_double: bool,
+    // Partial downloads modified more recently than this are spared.
+    download_grace: Duration,
+    // Verify music against checksum files, keeping those that fail.
+    verify_checksums: bool,
+}
+
 // Main entry point
 fn main() {
     // Parse the command line ...  and take the appropriate action(s).
-    let args = Args::parse();
+    // Usage errors exit with INVALID_ARGUMENTS; help and version are successes.
+    let args = Args::try_parse().unwrap_or_else(|e| {
+        let _ = e.print();
+        exit(if e.use_stderr() { INVALID_ARGUMENTS } else { SUCCESS });
+    });
+
+    // Every extension must belong to just one category; the built-in lists
+    // don't overlap, so only the additional art extensions need checking.
+    let art_extensions: Vec<String> =
+        args.art_ext.iter().map(|e| e.trim_start_matches('.').to_lowercase()).collect();
+    let conflicts = find_conflicting_types(&art_extensions);
+    if !conflicts.is_empty() {
+        for conflict in conflicts {
+            eprintln!("{}", style(format!("Conflicting file type: {}", conflict)).red());
+        }
+        exit(INVALID_ARGUMENTS);
+    }
 
     // List the Music and Audio File Types for the user's reference, then exit.
     if args.list_types { 
@@ -147,37 +928,134 @@ fn main() {
         exit(SUCCESS);
     }
 
+    // Inventory the library's file types, rather than purging anything.
+    if let Some(Command::Inventory { library_path }) = &args.command {
+        exit_if_path_missing("Library", library_path);
+        print_inventory(&build_inventory(&get_library_paths(library_path)));
+        exit(SUCCESS);
+    }
+
+    // Write folder art from embedded art, rather than purging anything.
+    if let Some(Command::Art { command: ArtCommand::Extract { library_path, name, purge, verbose } })
+        = &args.command
+    {
+        exit_if_path_missing("Library", library_path);
+        // Art recognized by the art options counts as a folder's art.
+        let art = ArtMatcher::new(
+            args.art_pattern.clone(), args.art_glob.clone(), args.art_ext.clone()
+        );
+        exit(extract_folder_art(library_path, &art, name, *purge, *verbose));
+    }
+
     // From here, we are actually doing the mlcp tasks.
 
     // Does Library Path exist?  
     let library_path = args.library_path.unwrap_or(String::from(NO_PATH));
-    if !Path::new(&library_path).exists() {
-        eprintln!("Library path \"{}\" does not exist.", library_path );
-        exit(PATH_DOES_NOT_EXIST);
-    }    
+    exit_if_path_missing("Library", &library_path);
         
     // Backups are enabled by specifying a BACKUP_PATH; is there one?
-    let backup_enabled = match args.backup_path { None => false, Some(_) => true };
+    let backup_enabled = args.backup_path.is_some();
     let backup_root = args.backup_path.unwrap_or(String::from(NO_PATH));
 
     // If specified, the BACKUP_PATH must exist!
-    if backup_enabled && !Path::new(&backup_root).exists() {
-        eprintln!("Backup path \"{}\" does not exist.", backup_root );
-        exit(PATH_DOES_NOT_EXIST);
+    if backup_enabled { exit_if_path_missing("Backup", &backup_root); }
+
+    // Refuse to run against a filesystem root, or to back up into the library
+    // being purged (where backups would be purged again on the next run).
+    if let Some(reason) = check_safety(&library_path, &backup_root, backup_enabled) {
+        eprintln!("{}", style(format!("Aborted: {}", reason)).red());
+        exit(SAFETY_ABORT);
     }
 
     // Get the files/directories for all items in the specified library_path
     let library_paths = get_library_paths(&library_path);
 
     // Build the PURGE file list ...
-    let purge_file_list = 
-        build_purge_file_list(library_paths, args.art, args.other_audio, args.documents);   
+    // Load any per-folder policy overrides; a broken file aborts the run,
+    // rather than risk purging files it was meant to keep.
+    let folder_configs = load_folder_configs(&library_paths).unwrap_or_else(|e| {
+        eprintln!("{}", style(e).red());
+        exit(INVALID_ARGUMENTS);
+    });
+    // A disc subfolder follows its album's policy, so only its rules apply.
+    for (folder, config) in &folder_configs {
+        if is_disc_folder(folder) && config.overrides_policy() {
+            eprintln!("{}", style(format!(
+                "[{}] Policy ignored in {}; a disc follows its album's policy (rules still apply)",
+                WARNING, folder.join(CONFIG_FILE_NAME).display()
+            )).yellow());
+        }
+    }
+
+    let options = PurgeOptions {
+        policy: CategoryPolicy {
+            art: args.art_mode.unwrap_or(if args.art { ArtMode::Purge } else { ArtMode::Keep }),
+            keep_other_audio: args.other_audio,
+            keep_documents: args.documents,
+            keep_rip_metadata: args.keep_rip_metadata,
+            keep_video: args.keep_video,
+            keep_playlists: args.keep_playlists,
+            keep_junk: args.keep_junk,
+        },
+        folder_configs,
+        library_root: get_library_root(&library_path),
+        include: args.include,
+        exclude: args.exclude,
+        art: ArtMatcher::new(args.art_pattern, args.art_glob, args.art_ext),
+        art_priority: args.art_priority.iter().map(|name| name.to_lowercase()).collect(),
+        art_name: args.art_name,
+        copy_art: args.copy_art,
+        rules: args.rule,
+        min_sizes: args.min_size,
+        // --max-art-size is shorthand for an art size limit.
+        max_sizes: args.max_size.into_iter()
+            .chain(args.max_art_size.map(|size| SizeLimit { category: FileCategory::Art, size }))
+            .collect(),
+        older_than: args.older_than,
+        newer_than: args.newer_than,
+        purge_apple_double: args.purge_apple_double,
+        download_grace: Duration::from_secs(args.download_grace.saturating_mul(60)),
+        verify_checksums: args.verify_checksums,
+    };
+    // Show the effective policy of each album it is overridden for (a disc
+    // subfolder follows its album's policy, so is reported with it) ...
+    let albums: BTreeSet<&Path> = options.folder_configs.keys()
+        .map(|folder| album_folder(folder))
+        .collect();
+    for album in albums {
+        print_verbose(
+            format!("[{}] {} ({})", POLICY, album.display(), effective_policy(album, &options)),
+            args.verbose
+        );
+    }
+    let plan = build_purge_file_list(library_paths, &options);
+    let purge_file_list = plan.purge_files;
+    // ... report the files that were spared, and why ...
+    for protected in &plan.protected_files {
+        print_verbose(
+            format!("[{}] {} ({})", PROTECTED, protected.path.display(), protected.reason),
+            args.verbose
+        );
+    }
+    // ... list folders whose checksums verified ...
+    for folder in &plan.verified_folders {
+        print_verbose(format!("[{}] {}", VERIFIED, folder.display()), args.verbose);
+    }
+    // ... list folders that could be purged as a whole ...
+    for folder in &plan.no_music_folders {
+        print_verbose(format!("[{}] {}", NO_MUSIC, folder.display()), args.verbose);
+    }
+    // ... warn about anything that can't be done ...
+    for warning in &plan.warnings {
+        eprintln!("{}", style(format!("[{}] {}", WARNING, warning)).yellow());
+    }
     // ... and process the resultant files ...    
     
     // Option to wrap the progress bar, so we can optionally create it based
     // on verbose value ...
+    let total_files = purge_file_list.len() + plan.art_renames.len();
     let bar: Option<ProgressBar> =
-        if !args.verbose { Some(ProgressBar::new(purge_file_list.len() as u64)) } else { None };
+        if !args.verbose { Some(ProgressBar::new(total_files as u64)) } else { None };
    
     if let Some(b) = &bar {
         b.set_style(ProgressStyle::default_bar()
@@ -187,9 +1065,10 @@ fn main() {
     // Get PathBuf instances for the two path strings ...
     let backup_dir = PathBuf::from(&backup_root);
     let source_dir = PathBuf::from(&library_path);
-    // Error and Processed File Counts (can be different to number of files reported from glob)
-    let mut err_count = 0;
+    // Errors and Processed File Count (can be different to number of files reported from glob)
+    let mut errors: Vec<PurgeError> = Vec::new();
     let mut proc_count = 0;
+    let mut junk_count = 0;
     // Which operation we're using.
     let op = 
         if args.purge && backup_enabled { BACKUP } else if args.purge { PURGE } else { SIMULATE };
@@ -199,14 +1078,18 @@ fn main() {
         let msg = opt_osstr_to_string(file.file_name(), NO_FILE_NAME);
         // Process the file
         match purge_or_backup_file(&file, &source_dir, &backup_dir, backup_enabled, args.purge) {
-            Ok(p) => { if args.verbose { println!("[{}] {}", op, p.display() ); } },
+            Ok(p) => {
+                if is_system_junk(&msg) { junk_count += 1; }
+                if args.verbose {
+                    match plan.purge_reasons.get(&file) {
+                        Some(reason) => println!("[{}] {} ({})", op, p.display(), reason),
+                        None => println!("[{}] {}", op, p.display()),
+                    }
+                }
+            },
             Err(e) => {
-                err_count += 1;
-                print_verbose(
-                    style(format!("[{}] {}", ERROR, e.display())).red().to_string(),
-                    args.verbose
-                );
-                
+                report_error(&e, &bar);
+                errors.push(e);
             }
         }  
         
@@ -217,18 +1100,226 @@ fn main() {
         }        
     }
 
+    // Rename (or copy) the kept art, once anything in its way has been purged.
+    let rename_op = if !args.purge { SIMULATE } else if options.copy_art { COPY } else { RENAME };
+    let mut rename_count = 0;
+    for rename in &plan.art_renames {
+        rename_count += 1;
+        let msg = opt_osstr_to_string(rename.from.file_name(), NO_FILE_NAME);
+        match rename_or_copy_file(
+            rename, &source_dir, &backup_dir, backup_enabled, options.copy_art, args.purge
+        ) {
+            Ok(_) => print_verbose(
+                format!("[{}] {} -> {}", rename_op, rename.from.display(), rename.to.display()),
+                args.verbose
+            ),
+            Err(e) => {
+                report_error(&e, &bar);
+                errors.push(e);
+            }
+        }
+
+        if let Some(b) = &bar {
+            b.set_message(msg);
+            b.inc(1);
+        }
+    }
+
     // Finish up the progress bar, if we are in non-verbose mode
     if let Some(b) = bar { b.finish(); }
     
-    let exit_msg;
-    if err_count == 0 { 
-        exit_msg = format!("{} files successfuly {}.", proc_count, op );
+    // Summarize the scope of the run, if it was narrowed by patterns ...
+    if !options.include.is_empty() {
+        print_verbose(format!("Included: {}", join_patterns(&options.include)), args.verbose);
+    }
+    if !options.exclude.is_empty() {
+        print_verbose(format!("Excluded: {}", join_patterns(&options.exclude)), args.verbose);
+    }
+    // ... errors are always reported, as they are not encoded in the exit code.
+    if junk_count > 0 {
+        print_verbose(format!("{} system junk files {}.", junk_count, op), args.verbose);
+    }
+    if errors.is_empty() { 
+        print_verbose(format!("{} files successfuly {}.", proc_count, op ), args.verbose);
+        if rename_count > 0 {
+            print_verbose(
+                format!("{} art files successfuly {}.", rename_count, rename_op), args.verbose
+            );
+        }
+    }
+    // Renames (or copies) are processed, and can fail, just as purges.
+    let proc_count = proc_count + rename_count;
+    if !errors.is_empty() {
+        eprintln!("{}", style(format!(
+            "{} errors out of {} files: {}.", errors.len(), proc_count, summarize_errors(&errors)
+        )).red());
+    }
+    exit(exit_code(errors.len(), proc_count));
+}
+
+// Reports an error; in the verbose listing, or around the progress bar.
+fn report_error(error: &PurgeError, bar: &Option<ProgressBar>) {
+    let err_msg = style(format!("[{}] {}", ERROR, error)).red().to_string();
+    match bar {
+        Some(b) => b.suspend(|| eprintln!("{}", err_msg)),
+        None => println!("{}", err_msg),
+    }
+}
+
+// Extracts folder art from embedded art, throughout the library; returns the
+// exit code.
+fn extract_folder_art(
+    library_path: &str,
+    art: &ArtMatcher,
+    name: &str,
+    write: bool,
+    verbose: bool,
+) -> i32 {
+    let library_paths = get_library_paths(library_path);
+    let (extractions, warnings) = plan_art_extraction(&library_paths, art, name);
+    for warning in &warnings {
+        eprintln!("{}", style(format!("[{}] {}", WARNING, warning)).yellow());
+    }
+
+    let op = if write { EXTRACT } else { SIMULATE };
+    let mut errors: Vec<PurgeError> = Vec::new();
+    for extraction in &extractions {
+        match extract_art(extraction, write) {
+            Ok(_) => print_verbose(
+                format!(
+                    "[{}] {} (from {})",
+                    op, extraction.target.display(),
+                    opt_osstr_to_string(extraction.source.file_name(), NO_FILE_NAME)
+                ),
+                verbose
+            ),
+            Err(e) => {
+                report_error(&e, &None);
+                errors.push(e);
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        print_verbose(format!("{} art files successfuly {}.", extractions.len(), op), verbose);
     } else {
-        exit_msg = 
-            style(format!("{} errors out of {} files.", err_count, proc_count)).red().to_string();       
+        eprintln!("{}", style(format!(
+            "{} errors out of {} art files: {}.",
+            errors.len(), extractions.len(), summarize_errors(&errors)
+        )).red());
+    }
+    exit_code(errors.len(), extractions.len())
+}
+
+// Plans the art to extract: for each album with music but no folder art (in
+// the album folder, or any of its discs), the first track (by path) with
+// embedded art, and the art file to write in the album folder.  Also returns
+// warnings, for art that can't be written.
+fn plan_art_extraction(
+    library_paths: &[PathBuf],
+    art: &ArtMatcher,
+    name: &str,
+) -> (Vec<ArtExtraction>, Vec<String>) {
+    // Only an art extension is replaced, so e.g. "my.cover" is kept whole.
+    let extension = opt_osstr_to_string(Path::new(name).extension(), NO_EXTENSION).to_lowercase();
+    let stem = if art.extensions.contains(&extension) {
+        opt_osstr_to_string(Path::new(name).file_stem(), name)
+    } else {
+        String::from(name)
+    };
+    let mut extractions = Vec::new();
+    let mut warnings = Vec::new();
+    for (album, (has_art, mut music)) in group_music_by_album(library_paths, art) {
+        if has_art { continue; }
+        music.sort();
+        let cover = music.into_iter().find_map(|file| {
+            let picture = tags::front_cover(tags::read_pictures(file).ok()?)?;
+            Some((file, picture))
+        });
+        let Some((source, picture)) = cover else { continue };
+
+        let Some(extension) = tags::image_extension(&picture.data) else {
+            warnings.push(format!(
+                "Not extracting art from {}; the image type is not recognized.", source.display()
+            ));
+            continue;
+        };
+        let target = album.join(format!("{}.{}", stem, extension));
+        if target.exists() {
+            warnings.push(format!(
+                "Not extracting art from {}; {} already exists.", source.display(), target.display()
+            ));
+            continue;
+        }
+        extractions.push(ArtExtraction { source: source.clone(), target });
+    }
+    (extractions, warnings)
+}
+
+// Writes the embedded front cover of a music file to a new art file; never
+// overwriting an existing file.  Nothing is written unless "write" is set.
+fn extract_art(extraction: &ArtExtraction, write: bool) -> Result<PathBuf, PurgeError> {
+    if !write { return Ok(extraction.target.clone()); }
+
+    let error = |source| PurgeError::Extract {
+        path: extraction.source.clone(), target: extraction.target.clone(), source
+    };
+    let picture = tags::read_pictures(&extraction.source).map(tags::front_cover).map_err(error)?
+        .ok_or_else(|| error(io::Error::new(io::ErrorKind::InvalidData, "no embedded art")))?;
+    let mut file = fs::File::options().write(true).create_new(true)
+        .open(&extraction.target).map_err(error)?;
+    io::Write::write_all(&mut file, &picture.data).map_err(error)?;
+    Ok(extraction.target.clone())
+}
+
+// Determines the exit code from the number of errors and processed files.
+fn exit_code(err_count: usize, proc_count: usize) -> i32 {
+    if proc_count == 0 { return NOTHING_TO_DO; }
+    if err_count == 0 { return SUCCESS; }
+    if err_count < proc_count { PARTIAL_FAILURE } else { TOTAL_FAILURE }
+}
+
+// Summarizes errors grouped by kind, most frequent first (e.g. "3 permission denied").
+fn summarize_errors(errors: &[PurgeError]) -> String {
+    let mut kind_counts: BTreeMap<String, usize> = BTreeMap::new();
+    for error in errors {
+        *kind_counts.entry(error.kind().to_string()).or_default() += 1;
+    }
+
+    let mut groups: Vec<(String, usize)> = kind_counts.into_iter().collect();
+    groups.sort_by_key(|(_, count)| Reverse(*count));
+    groups.iter()
+        .map(|(kind, count)| format!("{} {}", count, kind))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+// Checks the library and backup paths are safe to operate on, returning the
+// reason if they are not.
+fn check_safety(library_path: &str, backup_root: &str, backup_enabled: bool) -> Option<String> {
+    // Compare canonical paths, so relative paths and links can't hide a clash.
+    let library = fs::canonicalize(library_path).unwrap_or(PathBuf::from(library_path));
+    if library.parent().is_none() {
+        return Some(format!("library path \"{}\" is a filesystem root.", library_path));
+    }
+
+    if backup_enabled {
+        let backup = fs::canonicalize(backup_root).unwrap_or(PathBuf::from(backup_root));
+        if backup.starts_with(&library) {
+            return Some(format!(
+                "backup path \"{}\" is inside library path \"{}\".", backup_root, library_path
+            ));
+        }
+    }
+    None
+}
+
+// Reports a missing path, and exits, if the specified path does not exist.
+fn exit_if_path_missing(description: &str, path: &str) {
+    if !Path::new(path).exists() {
+        eprintln!("{} path \"{}\" does not exist.", description, path);
+        exit(PATH_DOES_NOT_EXIST);
     }
-    print_verbose(exit_msg, args.verbose);
-    exit(err_count);
 }
 
 // Get the paths of all the files that are in the libary.
@@ -237,7 +1328,7 @@ fn get_library_paths(library_path: &str) -> Vec<PathBuf> {
     let glob_path = Path::new(library_path).join(WILDCARD);
     let glob_path_str = glob_path.to_str().expect("Invalid library_path.");
     // ... get the full list of files and directories therein ...
-    let glob_paths = glob(&glob_path_str).expect("Glob request failed.");
+    let glob_paths = glob(glob_path_str).expect("Glob request failed.");
 
     // Package the paths a vector for later processing.
     let mut lib_paths = Vec::<PathBuf>::new();
@@ -245,28 +1336,135 @@ fn get_library_paths(library_path: &str) -> Vec<PathBuf> {
     lib_paths
 }
 
+// The library root, as a prefix of the paths returned by get_library_paths;
+// glob drops any leading "./", so it is dropped here too.
+fn get_library_root(library_path: &str) -> PathBuf {
+    Path::new(library_path).components()
+        .skip_while(|component| *component == Component::CurDir)
+        .collect()
+}
+
+// Finds any extension that is in more than one category's type list (or is
+// both an art extension and in another list), which would make whether it is
+// kept depend on the order the lists are checked in.  System junk is matched
+// by its whole name, before any extension, so can't conflict.
+fn find_conflicting_types(art_extensions: &[String]) -> Vec<String> {
+    let art_extensions: Vec<&str> = art_extensions.iter().map(String::as_str).collect();
+    let type_lists: [(&str, &[&str]); 9] = [
+        (FileCategory::Music.label(), &MUSIC_FILE_TYPES),
+        (FileCategory::Audio.label(), &AUDIO_FILE_TYPES),
+        (FileCategory::Document.label(), &DOCUMENT_FILE_TYPES),
+        (FileCategory::RipMetadata.label(), &RIP_METADATA_FILE_TYPES),
+        (FileCategory::Sidecar.label(), &SIDECAR_FILE_TYPES),
+        (FileCategory::Video.label(), &VIDEO_FILE_TYPES),
+        (FileCategory::Playlist.label(), &PLAYLIST_FILE_TYPES),
+        ("partial download", &PARTIAL_DOWNLOAD_TYPES),
+        (FileCategory::Art.label(), &art_extensions),
+    ];
+    let mut categories: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for (label, types) in type_lists {
+        for ext in types {
+            let labels = categories.entry(ext.to_lowercase()).or_default();
+            if !labels.contains(&label) { labels.push(label); }
+        }
+    }
+    categories.into_iter()
+        .filter(|(_, labels)| labels.len() > 1)
+        .map(|(ext, labels)| format!("\"{}\" is {}", ext, labels.join(" and ")))
+        .collect()
+}
+
 // Output the list of file types (extensions), for Music, Audio and Document files.
 fn list_types() {
    print_list("Music file types: ", &MUSIC_FILE_TYPES, true);
    print_list("Audio file types: ", &AUDIO_FILE_TYPES, false);
    print_list("Document/booklet file types: ", &DOCUMENT_FILE_TYPES, false); 
+   print_list("Rip metadata file types: ", &RIP_METADATA_FILE_TYPES, false);
+   print_list("Sidecar file types: ", &SIDECAR_FILE_TYPES, true);
+   print_list("Video file types: ", &VIDEO_FILE_TYPES, false);
+   print_list("Playlist file types: ", &PLAYLIST_FILE_TYPES, false);
+   print_list("Album art file types: ", &ALBUM_ART_EXTENSIONS, true);
+   print_list("System junk files: ", &SYSTEM_JUNK_FILES, false);
+   print_list("Partial download file types: ", &PARTIAL_DOWNLOAD_TYPES, false);
 }
 
-// Build the list of Album Art files to keep.
-fn build_keep_art_file_list(delete_art: bool) -> Vec<String> {
-    let mut art_file_list = Vec::new();
-    if !delete_art {
-        for fname in ALBUM_ART_FILENAMES {
-            for ext in ALBUM_ART_EXTENSIONS {
-                art_file_list.push( format!("{}.{}", fname, ext ));
-            }
-        }
+// Classifies a file extension by the category of type list it appears in.
+fn classify_extension(extension: &str) -> FileCategory {
+    let extension = extension.to_lowercase();
+    let ext = extension.as_str();
+    if MUSIC_FILE_TYPES.contains(&ext) { return FileCategory::Music; }
+    if AUDIO_FILE_TYPES.contains(&ext) { return FileCategory::Audio; }
+    if DOCUMENT_FILE_TYPES.contains(&ext) { return FileCategory::Document; }
+    if RIP_METADATA_FILE_TYPES.contains(&ext) { return FileCategory::RipMetadata; }
+    if SIDECAR_FILE_TYPES.contains(&ext) { return FileCategory::Sidecar; }
+    if VIDEO_FILE_TYPES.contains(&ext) { return FileCategory::Video; }
+    if PLAYLIST_FILE_TYPES.contains(&ext) { return FileCategory::Playlist; }
+    if ALBUM_ART_EXTENSIONS.contains(&ext) { return FileCategory::Art; }
+    FileCategory::Unknown
+}
+
+// Builds the inventory of every extension in the library, with the number
+// of files and their total size.  Extensions are lower-cased, so "MP3" and
+// "mp3" are counted together.
+fn build_inventory(library_paths: &Vec<PathBuf>) -> BTreeMap<String, InventoryEntry> {
+    let mut inventory: BTreeMap<String, InventoryEntry> = BTreeMap::new();
+    for file in library_paths {
+        // Only files are inventoried; directories are just containers.
+        if file.is_dir() { continue; }
+
+        let extension = opt_osstr_to_string(file.extension(), NO_EXTENSION).to_lowercase();
+        let entry = inventory.entry(extension).or_default();
+        entry.file_count += 1;
+        // A file we can't stat still counts; it just adds nothing to the size.
+        entry.total_size += fs::metadata(file).map(|m| m.len()).unwrap_or(0);
     }
-    art_file_list
+    inventory
+}
+
+// Outputs the inventory, one extension per line, followed by the totals.
+fn print_inventory(inventory: &BTreeMap<String, InventoryEntry>) {
+    println!("{:<12} {:>8} {:>12}  Type", "Extension", "Files", "Size");
+
+    let mut total_files = 0;
+    let mut total_size = 0;
+    for (extension, entry) in inventory {
+        let category = classify_extension(extension);
+        // Music is kept (green), unknown types are highlighted (yellow) as
+        // candidates for the type lists, and everything else is red.
+        let styled_label = match category {
+            FileCategory::Music => style(category.label()).green(),
+            FileCategory::Unknown => style(category.label()).yellow().bold(),
+            _ => style(category.label()).red(),
+        };
+        let label = if extension.is_empty() { NO_EXTENSION_LABEL } else { extension };
+        println!(
+            "{:<12} {:>8} {:>12}  {}",
+            label, entry.file_count, format_size(entry.total_size), styled_label
+        );
+        total_files += entry.file_count;
+        total_size += entry.total_size;
+    }
+
+    println!(
+        "{} extensions, {} files, {} total.",
+        inventory.len(), total_files, format_size(total_size)
+    );
+}
+
+// Parses an album art name pattern, as a case-insensitive regex.
+fn parse_art_pattern(pattern: &str) -> Result<Regex, String> {
+    RegexBuilder::new(pattern).case_insensitive(true).build()
+        .map_err(|e| format!("invalid art pattern \"{}\": {}", pattern, e))
 }
 
 // Builds the potential list of file extensions that we will be keeping.
-fn build_keep_extensions_list(keep_other_audio: bool, keep_documents: bool) -> Vec<String> {
+fn build_keep_extensions_list(
+    keep_other_audio: bool,
+    keep_documents: bool,
+    keep_rip_metadata: bool,
+    keep_video: bool,
+    keep_playlists: bool,
+) -> Vec<String> {
     let mut keep_extensions = Vec::new();
     // We always include the MUSIC file types.
     for ext in MUSIC_FILE_TYPES { keep_extensions.push(String::from(ext)) }
@@ -284,6 +1482,27 @@ fn build_keep_extensions_list(keep_other_audio: bool, keep_documents: bool) -> V
             keep_extensions.push(String::from(doc_ext));
         }
     }
+
+    // Add RIP METADATA file types (and rip playlists), if we are keeping them.
+    if keep_rip_metadata {
+        for rip_ext in RIP_METADATA_FILE_TYPES.iter().chain(&RIP_PLAYLIST_FILE_TYPES) {
+            keep_extensions.push(String::from(*rip_ext));
+        }
+    }
+
+    // Add VIDEO file types, if we are keeping them.
+    if keep_video {
+        for video_ext in VIDEO_FILE_TYPES {
+            keep_extensions.push(String::from(video_ext));
+        }
+    }
+
+    // Add PLAYLIST file types, if we are keeping them.
+    if keep_playlists {
+        for playlist_ext in PLAYLIST_FILE_TYPES {
+            keep_extensions.push(String::from(playlist_ext));
+        }
+    }
     keep_extensions
 }
 
@@ -293,9 +1512,14 @@ fn get_actual_extensions(
     library_paths: &Vec<PathBuf>,
     keep_other_audio: bool,
     keep_documents: bool,
+    keep_rip_metadata: bool,
+    keep_video: bool,
+    keep_playlists: bool,
 ) -> Vec<String> {
     // Build the list of extensions we want to keep, if they exist.
-    let keep_extensions = build_keep_extensions_list(keep_other_audio, keep_documents);
+    let keep_extensions = build_keep_extensions_list(
+        keep_other_audio, keep_documents, keep_rip_metadata, keep_video, keep_playlists
+    );
 
     // Now create a list of the extensions that ACTUALLY exist in the library.
     let mut extensions = Vec::new();
@@ -312,46 +1536,760 @@ fn get_actual_extensions(
 }
 
 // Builds the list of files to be purged.
-fn build_purge_file_list(
-    library_paths: Vec<PathBuf>,
-    delete_art: bool,
-    keep_other_audio: bool,
-    keep_documents: bool,
-) -> Vec<PathBuf> {
-    let mut purge_file_list = Vec::new();
-
-    // Get the list of art files and extensions we'll be keeping.
-    let art_file_list = build_keep_art_file_list(delete_art);    
-    // Get the list of actual extensions we will retain.
-    let actual_extensions = get_actual_extensions( &library_paths, keep_other_audio, keep_documents);
+fn build_purge_file_list(library_paths: Vec<PathBuf>, options: &PurgeOptions) -> PurgePlan {
+    let mut plan = PurgePlan::default();
+
+    // Load any ".mlcpignore" files, which protect files from being purged.
+    let ignore_files = load_ignore_files(&library_paths);
+    // The actual extensions we'll be keeping, by policy; there is one policy
+    // per distinct set of folder overrides.
+    let mut keep_extensions: HashMap<CategoryPolicy, Vec<String>> = HashMap::new();
+    let mut resource_forks: Vec<&PathBuf> = Vec::new();
+    // The art file to keep in each album that keeps only one, and the albums
+    // whose music all embeds art.
+    let chosen_art = choose_art(&library_paths, options);
+    let embedded_art_albums = find_embedded_art_albums(&library_paths, options);
+    // Art is only kept alongside music, and sidecars alongside their music
+    // file; so sidecars are decided last, once we know which music is kept.
+    let music_albums = find_music_albums(&library_paths);
+    let mut kept_music_stems: Option<HashSet<String>> = None;
+    let (sidecars, others): (Vec<&PathBuf>, Vec<&PathBuf>) =
+        library_paths.iter().partition(|file| is_sidecar(file));
+    // Files referenced by playlists are never purged.
+    let playlist_references = find_playlist_references(&library_paths, &mut plan);
+    plan.no_music_folders = find_folders_without_music(&library_paths, &options.library_root);
     
-    for file in library_paths {
+    for file in others.into_iter().chain(sidecars) {
         // Skip the file if it is a directory.
         if file.is_dir() { continue; }
+
+        // Skip the file if it is outside the included/excluded scope.
+        if !is_in_scope(file, options) { continue; }
         
         // Lossy conversion is fine; the part of the filename we're looking for
         // will always be UTF-8 (or won't be present).
         let file_name = opt_osstr_to_string(file.file_name(), NO_FILE_NAME);
          
-        // Don't delete resource forks, as they'll auto-delete when their
-        // parent file is removed.
-         if is_resource_fork(&file_name) { continue; }
+        // Resource forks are dealt with once we know which files are purged;
+        // files that merely have a fork's name are treated as any other.
+        if is_resource_fork(&file_name) && is_apple_double(file) {
+            resource_forks.push(file);
+            continue;
+        }
 
-        // Is this file on the list of art files to be kept?
-        if art_file_list.contains(&file_name.to_lowercase()) { continue; }
-        
-        // If it has an extension we're supposed to keep, keep it.        
-        if actual_extensions.contains(
-            &opt_osstr_to_string(file.extension(), NO_EXTENSION).to_lowercase()
-        )
+        // Never purge our own ignore and configuration files.
+        if file_name == IGNORE_FILE_NAME || file_name == CONFIG_FILE_NAME { continue; }
+
+        // Get the extensions to keep, under this file's policy; a file in a
+        // disc subfolder follows its album's policy, so an album is treated
+        // as a whole.
+        let folder = file.parent().unwrap_or(Path::new(NO_PATH));
+        let album = album_folder(folder);
+        let policy = effective_policy(album, options);
+        let actual_extensions = keep_extensions.entry(policy).or_insert_with(|| {
+            get_actual_extensions(
+                &library_paths, policy.keep_other_audio, policy.keep_documents,
+                policy.keep_rip_metadata, policy.keep_video, policy.keep_playlists
+            )
+        });
+
+        // Is this album art we're keeping, or does it have an extension we're
+        // supposed to keep?
+        let extension = opt_osstr_to_string(file.extension(), NO_EXTENSION).to_lowercase();
+        // With "--art-mode one", only the album's chosen art is kept.
+        let is_art = options.art.is_art(&file_name);
+        let (kept_art, art_reason) = match (policy.art, is_art) {
+            (ArtMode::Purge, _) | (_, false) => (false, None),
+            _ if !music_albums.contains(album) => {
+                (false, Some(String::from("no music in the art's album")))
+            }
+            (ArtMode::Keep, true) => (true, None),
+            (ArtMode::One, true) => match chosen_art.get(album) {
+                Some(chosen) if chosen != file => (false, Some(format!(
+                    "--art-mode one keeps {}", opt_osstr_to_string(chosen.file_name(), NO_FILE_NAME)
+                ))),
+                _ => (true, None),
+            },
+            (ArtMode::IfEmbedded, true) if embedded_art_albums.contains(album) => {
+                (false, Some(String::from("all of the album's music embeds art")))
+            }
+            (ArtMode::IfEmbedded, true) => (true, None),
+        };
+        // System junk is its own category, whatever its extension; sidecars
+        // are kept only if they have a music file of the same name.
+        let junk = is_system_junk(&file_name);
+        let sidecar = is_sidecar(file);
+        let kept_by_type = if junk {
+            policy.keep_junk
+        } else if sidecar {
+            kept_music_stems
+                .get_or_insert_with(|| find_kept_music_stems(&library_paths, &plan.purge_files))
+                .contains(&file_stem(file))
+        } else {
+            kept_art || actual_extensions.contains(&extension)
+        };
+        let partial = is_partial_download(&file_name, &extension);
+        let type_reason = match (junk, partial) {
+            (true, _) => Some(String::from("system junk")),
+            (_, true) => Some(String::from("partial download")),
+            _ if sidecar => Some(String::from("no kept music file of the same name")),
+            _ => art_reason,
+        };
+
+        // The first matching rule, if any, overrides the file type; otherwise
+        // files we'd keep are still purged if they are outside the size limits.
+        let (purge, reason) = match match_rules(file, options) {
+            Some((rule, reason)) => (rule.action == RuleAction::Purge, Some(reason)),
+            None if kept_by_type => {
+                let category =
+                    if kept_art { FileCategory::Art } else { classify_extension(&extension) };
+                let reason = size_limit_reason(file, category, options);
+                (reason.is_some(), reason)
+            }
+            None => (true, type_reason),
+        };
+        if !purge {
+            // Report crud that a rule kept.
+            if let (Some(reason), false) = (reason, kept_by_type) {
+                plan.protected_files.push(ProtectedFile { path: file.to_path_buf(), reason });
+            }
+            continue;
+        }
+
+        // It is crud, but is it referenced by a playlist ...
+        if let Some(playlist) = playlist_references.get(&playlists::resolve(file)) {
+            let reason = format!("referenced by playlist {}", playlist);
+            plan.protected_files.push(ProtectedFile { path: file.to_path_buf(), reason });
+            continue;
+        }
+
+        // ... a download that may still be in progress ...
+        if let Some(reason) = partial.then(|| download_grace_reason(file, options)).flatten() {
+            plan.protected_files.push(ProtectedFile { path: file.to_path_buf(), reason });
+            continue;
+        }
+
+        // ... was it modified outside the time window ...
+        if let Some(reason) = time_limit_reason(file, options) {
+            plan.protected_files.push(ProtectedFile { path: file.to_path_buf(), reason });
+            continue;
+        }
+
+        // ... or is it protected by an ignore file?
+        if let Some(reason) = ignore_reason(&ignore_files, file) {
+            plan.protected_files.push(ProtectedFile { path: file.to_path_buf(), reason });
+            continue;
+        }
+
+        if let Some(reason) = reason {
+            plan.purge_reasons.insert(file.to_path_buf(), reason);
+        }
+        plan.purge_files.push(file.to_path_buf()); 
+    }
+
+    plan_checksum_verification(&library_paths, options, &mut plan);
+    plan_resource_forks(&resource_forks, &ignore_files, options, &mut plan);
+    plan_art_renames(&chosen_art, &library_paths, options, &mut plan);
+    plan
+}
+
+// Finds every file referenced by a playlist in the library, with the name of
+// (the first) playlist referencing it; warning of any that are missing.
+// Every playlist is read, even one outside the scope (or protected by an
+// ignore file), as it stays in the library and must not be left broken.
+fn find_playlist_references(
+    library_paths: &[PathBuf],
+    plan: &mut PurgePlan,
+) -> HashMap<PathBuf, String> {
+    let mut references: HashMap<PathBuf, String> = HashMap::new();
+    for playlist in library_paths {
+        let extension = opt_osstr_to_string(playlist.extension(), NO_EXTENSION).to_lowercase();
+        let name = opt_osstr_to_string(playlist.file_name(), NO_FILE_NAME);
+        if !PLAYLIST_FILE_TYPES.contains(&extension.as_str())
+            || playlist.is_dir() || (is_resource_fork(&name) && is_apple_double(playlist))
         {
             continue;
-        }   
+        }
 
-        purge_file_list.push(file); 
+        let entries = playlists::read_entries(playlist).unwrap_or_else(|error| {
+            plan.warnings.push(
+                format!("Could not read playlist: {} ({})", playlist.display(), error)
+            );
+            Vec::new()
+        });
+        for entry in entries {
+            if !entry.exists() {
+                plan.warnings.push(
+                    format!("Playlist entry missing: {} (in {})", entry.display(), name)
+                );
+            }
+            references.entry(entry).or_insert_with(|| name.clone());
+        }
     }
-    
-    purge_file_list
+    references
+}
+
+// Verifies the music in each folder against its checksum files (with
+// "--verify-checksums"), reporting each failure.  The checksum files of a
+// folder that fails are kept, so the music can be checked (or repaired) again.
+fn plan_checksum_verification(
+    library_paths: &[PathBuf],
+    options: &PurgeOptions,
+    plan: &mut PurgePlan,
+) {
+    if !options.verify_checksums { return; }
+    let mut checksum_files: BTreeMap<&Path, Vec<&PathBuf>> = BTreeMap::new();
+    for file in library_paths {
+        let extension = opt_osstr_to_string(file.extension(), NO_EXTENSION).to_lowercase();
+        let file_name = opt_osstr_to_string(file.file_name(), NO_FILE_NAME);
+        if !checksums::CHECKSUM_FILE_TYPES.contains(&extension.as_str())
+            || file.is_dir() || !is_in_scope(file, options)
+            || (is_resource_fork(&file_name) && is_apple_double(file))
+        {
+            continue;
+        }
+        let folder = file.parent().unwrap_or(Path::new(NO_PATH));
+        checksum_files.entry(folder).or_default().push(file);
+    }
+
+    for (folder, files) in checksum_files {
+        let mut verified = true;
+        for &file in &files {
+            let checksum_name = opt_osstr_to_string(file.file_name(), NO_FILE_NAME);
+            match checksums::verify(file) {
+                Ok(failures) => for failure in failures {
+                    verified = false;
+                    plan.warnings.push(format!(
+                        "Checksum failed: {} ({}, in {})",
+                        failure.file.display(), failure.problem, checksum_name
+                    ));
+                },
+                Err(error) => {
+                    verified = false;
+                    plan.warnings.push(
+                        format!("Could not read checksums: {} ({})", file.display(), error)
+                    );
+                }
+            }
+        }
+        if verified {
+            plan.verified_folders.push(folder.to_path_buf());
+            continue;
+        }
+
+        // Keep the checksum files of a folder that failed.
+        for &file in &files {
+            if let Some(index) = plan.purge_files.iter().position(|purge| purge == file) {
+                plan.purge_files.remove(index);
+                plan.purge_reasons.remove(file);
+                plan.protected_files.push(ProtectedFile {
+                    path: file.to_path_buf(),
+                    reason: String::from("its folder failed checksum verification"),
+                });
+            }
+        }
+    }
+}
+
+// Plans the purging of resource forks (AppleDouble "._" files).  macOS
+// removes a fork along with its file, but not on other systems; so forks are
+// purged with their file, or if their file is missing (or if purging them
+// all).  Each fork is purged before its file, so it is never found missing.
+fn plan_resource_forks(
+    resource_forks: &[&PathBuf],
+    ignore_files: &[Gitignore],
+    options: &PurgeOptions,
+    plan: &mut PurgePlan,
+) {
+    let purged: HashSet<PathBuf> = plan.purge_files.iter().cloned().collect();
+    let mut forks_by_file: HashMap<PathBuf, PathBuf> = HashMap::new();
+    let mut orphans: Vec<PathBuf> = Vec::new();
+    for &fork in resource_forks {
+        // The fork's name is that of its file, with a "._" prefix.
+        let file_name = opt_osstr_to_string(fork.file_name(), NO_FILE_NAME);
+        let file = fork.with_file_name(&file_name[2..]);
+        let file_exists = file.exists();
+        let reason = if purged.contains(&file) {
+            format!("AppleDouble file for purged {}", &file_name[2..])
+        } else if !file_exists {
+            format!("AppleDouble file for missing {}", &file_name[2..])
+        } else if options.purge_apple_double {
+            String::from("AppleDouble file (--purge-apple-double)")
+        } else {
+            continue;
+        };
+
+        // Forks are protected just as any other file.
+        if let Some(reason) =
+            time_limit_reason(fork, options).or_else(|| ignore_reason(ignore_files, fork))
+        {
+            plan.protected_files.push(ProtectedFile { path: fork.to_path_buf(), reason });
+            continue;
+        }
+        plan.purge_reasons.insert(fork.to_path_buf(), reason);
+        if file_exists {
+            forks_by_file.insert(file, fork.to_path_buf());
+        } else {
+            orphans.push(fork.to_path_buf());
+        }
+    }
+
+    let mut purge_files = Vec::new();
+    for file in plan.purge_files.drain(..) {
+        purge_files.extend(forks_by_file.remove(&file));
+        purge_files.push(file);
+    }
+    // Forks of files that are kept (with --purge-apple-double), or missing.
+    let mut remaining: Vec<PathBuf> = forks_by_file.into_values().chain(orphans).collect();
+    remaining.sort();
+    purge_files.extend(remaining);
+    plan.purge_files = purge_files;
+}
+
+// Plans the renaming (or copying) of each folder's chosen art to the canonical
+// art name.  Only the name and the case of the extension are changed, so art
+// in another format is left alone, with a warning.
+fn plan_art_renames(
+    chosen_art: &HashMap<PathBuf, PathBuf>,
+    library_paths: &[PathBuf],
+    options: &PurgeOptions,
+    plan: &mut PurgePlan,
+) {
+    let Some(art_name) = &options.art_name else { return };
+    // The files being kept in each folder with chosen art, which a rename
+    // mustn't overwrite.
+    let purged: HashSet<&PathBuf> = plan.purge_files.iter().collect();
+    let art_folders: HashSet<&Path> = chosen_art.values().filter_map(|art| art.parent()).collect();
+    let mut kept_files: HashMap<&Path, Vec<&PathBuf>> = HashMap::new();
+    for file in library_paths {
+        let Some(folder) = file.parent() else { continue };
+        if art_folders.contains(folder) && !purged.contains(file) {
+            kept_files.entry(folder).or_default().push(file);
+        }
+    }
+
+    let mut renames: Vec<ArtRename> = Vec::new();
+    for chosen in chosen_art.values() {
+        // Art that is purged, or out of scope, is left alone.
+        if !is_in_scope(chosen, options) || purged.contains(chosen) { continue; }
+        // The art is renamed in place, even in a disc subfolder.
+        let folder = chosen.parent().unwrap_or(Path::new(NO_PATH));
+
+        // Without an extension, the canonical name keeps the art's own (lower-case).
+        let chosen_name = opt_osstr_to_string(chosen.file_name(), NO_FILE_NAME);
+        let extension = opt_osstr_to_string(chosen.extension(), NO_EXTENSION).to_lowercase();
+        let target_name = match Path::new(art_name).extension() {
+            Some(_) => art_name.clone(),
+            None => format!("{}.{}", art_name, extension),
+        };
+        let target_extension =
+            opt_osstr_to_string(Path::new(&target_name).extension(), NO_EXTENSION);
+        if art_format(&target_extension) != art_format(&extension) {
+            plan.warnings.push(format!(
+                "Not renaming {} to {}; it is not a {} image.",
+                chosen.display(), target_name, target_extension.to_lowercase()
+            ));
+            continue;
+        }
+
+        // Nothing to do if the art already has its name, or (for a copy) has
+        // it in a different case, which would be the same file on some systems.
+        if chosen_name == target_name
+            || (options.copy_art && chosen_name.eq_ignore_ascii_case(&target_name)) { continue; }
+
+        // Never overwrite a file that is being kept; in any case, as names
+        // differing only in case are the same file on some systems.
+        let target = folder.join(&target_name);
+        let taken = kept_files.get(folder).into_iter().flatten().any(|&file| {
+            file != chosen
+                && opt_osstr_to_string(file.file_name(), NO_FILE_NAME)
+                    .eq_ignore_ascii_case(&target_name)
+        });
+        if taken {
+            plan.warnings.push(format!(
+                "Not renaming {} to {}; the file already exists.", chosen.display(), target_name
+            ));
+            continue;
+        }
+        renames.push(ArtRename { from: chosen.clone(), to: target });
+    }
+    // Folders come out of the map in any order; list them in path order.
+    renames.sort_by(|a, b| a.from.cmp(&b.from));
+    plan.art_renames = renames;
+}
+
+// The image format implied by an art extension, so e.g. "JPEG" and "jpg" match.
+fn art_format(extension: &str) -> String {
+    match extension.to_lowercase().as_str() {
+        "jpeg" => String::from("jpg"),
+        "tif" => String::from("tiff"),
+        other => String::from(other),
+    }
+}
+
+// Chooses the best ranked art file in each album whose policy is "--art
+// one", or that keeps its art when art is being renamed; an album's disc
+// subfolders are included, so it has a single art file.
+fn choose_art(library_paths: &[PathBuf], options: &PurgeOptions) -> HashMap<PathBuf, PathBuf> {
+    let mut candidates: HashMap<PathBuf, Vec<&PathBuf>> = HashMap::new();
+    for file in library_paths {
+        if file.is_dir() { continue; }
+        let album = album_folder(file.parent().unwrap_or(Path::new(NO_PATH)));
+        // Art is chosen to keep only one, or to give the kept art its canonical name.
+        match effective_policy(album, options).art {
+            ArtMode::One => {},
+            ArtMode::Keep | ArtMode::IfEmbedded if options.art_name.is_some() => {},
+            _ => continue,
+        }
+        // Art that would be purged anyway, by a rule or size limit, can't be
+        // the one that is kept.
+        if options.art.is_art(&opt_osstr_to_string(file.file_name(), NO_FILE_NAME))
+            && survives_art_limits(file, options)
+        {
+            candidates.entry(album.to_path_buf()).or_default().push(file);
+        }
+    }
+
+    candidates.into_iter()
+        .filter_map(|(album, files)| {
+            // Ties keep the first file by name, so the choice is repeatable.
+            let best = files.into_iter().min_by_key(|file| (art_rank(file, options), *file))?;
+            Some((album, best.clone()))
+        })
+        .collect()
+}
+
+// Finds the albums, with art and a policy of "--art-mode if-embedded", in which
+// every music file (on every disc) has embedded art.  Music in a format whose
+// tags can't be read counts as having no art, so its album's art is kept.
+fn find_embedded_art_albums(library_paths: &[PathBuf], options: &PurgeOptions) -> HashSet<PathBuf> {
+    group_music_by_album(library_paths, &options.art).into_iter()
+        .filter(|(album, (has_art, music))| {
+            *has_art && !music.is_empty()
+                && effective_policy(album, options).art == ArtMode::IfEmbedded
+        })
+        .filter(|(_, (_, music))| music.iter().all(|file| has_embedded_art(file)))
+        .map(|(folder, _)| folder.to_path_buf())
+        .collect()
+}
+
+// Finds the albums in which art is kept: the folders holding music (or
+// other audio) files, or disc subfolders holding them.
+fn find_music_albums(library_paths: &[PathBuf]) -> HashSet<PathBuf> {
+    library_paths.iter()
+        .filter(|file| is_audio_file(file))
+        .filter_map(|file| file.parent())
+        .map(|folder| album_folder(folder).to_path_buf())
+        .collect()
+}
+
+// Finds the path, without its extension, of every music (or other audio)
+// file that isn't being purged; as matched by a sidecar file's own.
+fn find_kept_music_stems(library_paths: &[PathBuf], purge_files: &[PathBuf]) -> HashSet<String> {
+    let purged: HashSet<&PathBuf> = purge_files.iter().collect();
+    library_paths.iter()
+        .filter(|file| is_audio_file(file) && !purged.contains(file))
+        .map(|file| file_stem(file))
+        .collect()
+}
+
+// Determines whether a file is a sidecar, kept only with its music file.
+fn is_sidecar(file: &Path) -> bool {
+    let extension = opt_osstr_to_string(file.extension(), NO_EXTENSION).to_lowercase();
+    SIDECAR_FILE_TYPES.contains(&extension.as_str())
+}
+
+// A file's path without its extension, lower-cased; so sidecars are matched
+// to their music file regardless of case.
+fn file_stem(file: &Path) -> String {
+    file.with_extension("").to_string_lossy().to_lowercase()
+}
+
+// Finds the top-most folders, below the library root, with no music (or
+// other audio) files anywhere in them.
+fn find_folders_without_music(library_paths: &[PathBuf], library_root: &Path) -> Vec<PathBuf> {
+    let below_root = |folder: &&Path| folder.starts_with(library_root) && *folder != library_root;
+    let mut with_music: HashSet<&Path> = HashSet::new();
+    for file in library_paths.iter().filter(|file| is_audio_file(file)) {
+        with_music.extend(file.ancestors().skip(1).take_while(below_root));
+    }
+
+    let mut without_music: HashSet<&Path> = HashSet::new();
+    for file in library_paths {
+        for folder in file.ancestors().skip(1).take_while(below_root) {
+            // Only report a folder if its parent does have music; an empty
+            // disc is still part of its album.
+            let parent = folder.parent().unwrap_or(Path::new(NO_PATH));
+            if !with_music.contains(folder)
+                && (parent == library_root || with_music.contains(parent))
+                && !(is_disc_folder(folder) && with_music.contains(parent))
+            {
+                without_music.insert(folder);
+            }
+        }
+    }
+    let mut without_music: Vec<PathBuf> =
+        without_music.into_iter().map(Path::to_path_buf).collect();
+    without_music.sort();
+    without_music
+}
+
+// Determines whether a file is music, or other audio.
+fn is_audio_file(file: &Path) -> bool {
+    !file.is_dir() && matches!(
+        classify_extension(&opt_osstr_to_string(file.extension(), NO_EXTENSION)),
+        FileCategory::Music | FileCategory::Audio
+    )
+}
+
+// The album a folder belongs to; its parent, for a disc subfolder.
+fn album_folder(folder: &Path) -> &Path {
+    match folder.parent() {
+        Some(album) if is_disc_folder(folder) => album,
+        _ => folder,
+    }
+}
+
+// Determines whether a folder is one disc of a multi-disc album.
+fn is_disc_folder(folder: &Path) -> bool {
+    static DISC_FOLDER: OnceLock<Regex> = OnceLock::new();
+    let pattern = DISC_FOLDER.get_or_init(|| {
+        RegexBuilder::new(DISC_FOLDER_PATTERN).case_insensitive(true).build()
+            .expect("Invalid disc folder pattern.")
+    });
+    pattern.is_match(&opt_osstr_to_string(folder.file_name(), NO_FILE_NAME))
+}
+
+// Groups the music files in the library by album (including any disc
+// subfolders), noting whether each album has any folder-level art.
+fn group_music_by_album<'a>(
+    library_paths: &'a [PathBuf],
+    art: &ArtMatcher,
+) -> BTreeMap<&'a Path, (bool, Vec<&'a PathBuf>)> {
+    let mut albums: BTreeMap<&Path, (bool, Vec<&PathBuf>)> = BTreeMap::new();
+    for file in library_paths {
+        if file.is_dir() { continue; }
+        let album = album_folder(file.parent().unwrap_or(Path::new(NO_PATH)));
+        let extension = opt_osstr_to_string(file.extension(), NO_EXTENSION);
+        let (has_art, music) = albums.entry(album).or_default();
+        if classify_extension(&extension) == FileCategory::Music {
+            music.push(file);
+        } else if art.is_art(&opt_osstr_to_string(file.file_name(), NO_FILE_NAME)) {
+            *has_art = true;
+        }
+    }
+    albums
+}
+
+// Determines whether a music file has any embedded art.
+fn has_embedded_art(file: &Path) -> bool {
+    tags::read_pictures(file).map(|pictures| !pictures.is_empty()).unwrap_or(false)
+}
+
+// Ranks an art file for "--art-mode one"; lower is better.  Art in the album
+// folder itself ranks above any in its disc subfolders; then files are ranked
+// by their name's priority, then by resolution and file size (largest first).
+fn art_rank(file: &Path, options: &PurgeOptions) -> (bool, usize, Reverse<u64>, Reverse<u64>) {
+    let in_disc_folder = is_disc_folder(file.parent().unwrap_or(Path::new(NO_PATH)));
+    let stem = opt_osstr_to_string(file.file_stem(), NO_FILE_NAME).to_lowercase();
+    let priority = options.art_priority.iter().position(|name| *name == stem)
+        .unwrap_or(options.art_priority.len());
+    // Unreadable (or unsupported) images rank below any readable one.
+    let pixels = imagesize::size(file)
+        .map(|size| size.width as u64 * size.height as u64)
+        .unwrap_or(0);
+    let size = fs::metadata(file).map(|m| m.len()).unwrap_or(0);
+    (in_disc_folder, priority, Reverse(pixels), Reverse(size))
+}
+
+// Loads every ".mlcp.toml" file in the library, keyed by the folder it
+// overrides the policy for.
+fn load_folder_configs(
+    library_paths: &[PathBuf]
+) -> Result<BTreeMap<PathBuf, FolderConfig>, String> {
+    let mut folder_configs = BTreeMap::new();
+    for path in library_paths {
+        if path.file_name() != Some(OsStr::new(CONFIG_FILE_NAME)) || path.is_dir() { continue; }
+
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let config: FolderConfig = toml::from_str(&text)
+            .map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+        folder_configs.insert(path.parent().unwrap_or(Path::new(NO_PATH)).to_path_buf(), config);
+    }
+    Ok(folder_configs)
+}
+
+// Determines the policy for a folder: the command line policy, overridden by
+// each ".mlcp.toml" from the library root down, so the closest file wins.
+fn effective_policy(folder: &Path, options: &PurgeOptions) -> CategoryPolicy {
+    // Paths sort parents-first, so ancestors are applied from the top down.
+    options.folder_configs.iter()
+        .filter(|(config_folder, _)| folder.starts_with(config_folder))
+        .fold(options.policy, |policy, (_, config)| config.apply(policy))
+}
+
+// Finds the first rule matching a file, with the reason for the decision.
+// Rules from ".mlcp.toml" files are checked first, closest folder first,
+// followed by those from the command line.
+fn match_rules<'a>(file: &Path, options: &'a PurgeOptions) -> Option<(&'a Rule, String)> {
+    let folder = file.parent().unwrap_or(Path::new(NO_PATH));
+    let folder_rules = options.folder_configs.iter().rev()
+        .filter(|(config_folder, _)| folder.starts_with(config_folder))
+        .flat_map(|(config_folder, config)| {
+            let source = config_folder.join(CONFIG_FILE_NAME).display().to_string();
+            config.rules.iter().map(move |rule| (rule, source.clone()))
+        });
+    let command_line_rules = options.rules.iter().map(|rule| (rule, String::from("--rule")));
+    let mut rules = folder_rules.chain(command_line_rules).peekable();
+
+    // Only gather the facts (which needs the file's metadata) if there are rules.
+    rules.peek()?;
+    let facts = get_file_facts(file, options);
+    rules.find(|(rule, _)| rule.matches(&facts))
+        .map(|(rule, source)| (rule, format!("rule \"{}\" from {}", rule, source)))
+}
+
+// Determines whether art that would be kept survives the rules and the art
+// size limits; as the planner checks them for kept art.
+fn survives_art_limits(file: &Path, options: &PurgeOptions) -> bool {
+    match match_rules(file, options) {
+        Some((rule, _)) => rule.action != RuleAction::Purge,
+        None => size_limit_reason(file, FileCategory::Art, options).is_none(),
+    }
+}
+
+// Determines whether a file, of a category that would be kept, is outside
+// that category's size limits, returning the reason if it is.
+fn size_limit_reason(
+    file: &Path,
+    category: FileCategory,
+    options: &PurgeOptions
+) -> Option<String> {
+    let mut min_sizes = options.min_sizes.iter().filter(|l| l.category == category);
+    let mut max_sizes = options.max_sizes.iter().filter(|l| l.category == category);
+    // Only get the file's size if there are limits for its category.
+    min_sizes.clone().chain(max_sizes.clone()).next()?;
+    let size = fs::metadata(file).ok()?.len();
+
+    if let Some(limit) = min_sizes.find(|l| size < l.size) {
+        return Some(format!("{} smaller than --min-size {}", format_size(size), limit));
+    }
+    max_sizes.find(|l| size > l.size)
+        .map(|limit| format!("{} larger than --max-size {}", format_size(size), limit))
+}
+
+// Determines whether a file was modified outside the --older-than and
+// --newer-than window, returning the reason if it was.
+fn time_limit_reason(file: &Path, options: &PurgeOptions) -> Option<String> {
+    if options.older_than.is_none() && options.newer_than.is_none() { return None; }
+
+    // A file without a modification time can't be shown to be in the window.
+    let modified = match fs::metadata(file).and_then(|m| m.modified()) {
+        Ok(modified) => modified,
+        Err(_) => return Some(String::from("modification time unknown")),
+    };
+    let age = format_age(SystemTime::now().duration_since(modified).unwrap_or_default());
+    if let Some(limit) = options.older_than.as_ref().filter(|l| modified >= l.time) {
+        return Some(format!("modified {} ago, not --older-than {}", age, limit.text));
+    }
+    options.newer_than.as_ref().filter(|l| modified <= l.time)
+        .map(|limit| format!("modified {} ago, not --newer-than {}", age, limit.text))
+}
+
+// Explains why a partial download is spared, if it was modified within the
+// download grace period.
+fn download_grace_reason(file: &Path, options: &PurgeOptions) -> Option<String> {
+    if options.download_grace.is_zero() { return None; }
+
+    // A file without a modification time may be being written right now.
+    let modified = match fs::metadata(file).and_then(|m| m.modified()) {
+        Ok(modified) => modified,
+        Err(_) => return Some(String::from("modification time unknown")),
+    };
+    let age = SystemTime::now().duration_since(modified).unwrap_or_default();
+    (age < options.download_grace).then(|| format!(
+        "partial download modified {} ago, within --download-grace {}",
+        format_age(age), format_age(options.download_grace)
+    ))
+}
+
+// Gathers the facts about a file that rules are evaluated against.
+fn get_file_facts(file: &Path, options: &PurgeOptions) -> FileFacts {
+    // A file we can't stat is treated as empty, and brand new.
+    let metadata = fs::metadata(file).ok();
+    let modified = metadata.as_ref().and_then(|m| m.modified().ok());
+    FileFacts {
+        ext: opt_osstr_to_string(file.extension(), NO_EXTENSION).to_lowercase(),
+        name: opt_osstr_to_string(file.file_name(), NO_FILE_NAME),
+        path: file.strip_prefix(&options.library_root).unwrap_or(file).to_string_lossy().into(),
+        size: metadata.map(|m| m.len()).unwrap_or(0),
+        age: modified
+            .and_then(|m| SystemTime::now().duration_since(m).ok())
+            .unwrap_or_default(),
+    }
+}
+
+// Determines whether a file is within the scope set by the include/exclude
+// patterns, matched against its path relative to the library root.
+fn is_in_scope(file: &Path, options: &PurgeOptions) -> bool {
+    let relative_path = file.strip_prefix(&options.library_root).unwrap_or(file);
+    let matches = |p: &Pattern| p.matches_path_with(relative_path, PATH_MATCH_OPTIONS);
+
+    if !options.include.is_empty() && !options.include.iter().any(matches) { return false; }
+    !options.exclude.iter().any(matches)
+}
+
+// Loads every ".mlcpignore" file in the library, deepest folders first, so
+// the closest ignore file to a given path takes precedence (as in git).
+fn load_ignore_files(library_paths: &[PathBuf]) -> Vec<Gitignore> {
+    let mut ignore_files = Vec::new();
+    for path in library_paths {
+        if path.file_name() != Some(OsStr::new(IGNORE_FILE_NAME)) || path.is_dir() { continue; }
+
+        // Patterns are relative to the folder containing the ignore file.
+        let mut builder = GitignoreBuilder::new(path.parent().unwrap_or(Path::new(NO_PATH)));
+        // Bad patterns are reported, but the rest of the file still applies.
+        if let Some(e) = builder.add(path) { eprintln!("Warning: {}", e); }
+        match builder.build() {
+            Ok(ignore_file) => ignore_files.push(ignore_file),
+            Err(e) => eprintln!("Warning: {}", e),
+        }
+    }
+    ignore_files.sort_by_key(|i| std::cmp::Reverse(i.path().components().count()));
+    ignore_files
+}
+
+// Determines whether a file is protected by an ignore file, returning the
+// reason (ignore file and pattern) if it is.
+fn ignore_reason(ignore_files: &[Gitignore], file: &Path) -> Option<String> {
+    for ignore_file in ignore_files {
+        // Only ignore files in the file's own folder, or above, apply.
+        if !file.starts_with(ignore_file.path()) { continue; }
+
+        match ignore_file.matched_path_or_any_parents(file, false) {
+            Match::Ignore(glob) => {
+                let from = glob.from().unwrap_or(ignore_file.path());
+                return Some(format!("{}: {}", from.display(), glob.original()));
+            }
+            // A negated ("!") pattern un-protects the file; don't look further.
+            Match::Whitelist(_) => return None,
+            Match::None => continue,
+        }
+    }
+    None
+}
+
+// Determines if a file is a partial download or temporary file, by its
+// (lower-case) extension or its name's prefix.
+fn is_partial_download(file_name: &str, extension: &str) -> bool {
+    PARTIAL_DOWNLOAD_TYPES.contains(&extension)
+        || PARTIAL_DOWNLOAD_PREFIXES.iter().any(|prefix| file_name.starts_with(prefix))
+}
+
+// Determines if a file name is that of system junk (e.g. ".DS_Store").
+fn is_system_junk(file_name: &str) -> bool {
+    SYSTEM_JUNK_FILES.iter().any(|junk| junk.eq_ignore_ascii_case(file_name))
 }
 
 // Determines if the file_name indicates a macOS resource fork (i.e. starts with "._").
@@ -360,42 +2298,81 @@ fn is_resource_fork(file_name: &str) -> bool {
     if file_name.len() <2 { return false; }
     // Look at the characters individually, so as to prevent subscript issues
     // with multi-byte characters.
-    if  file_name.chars().nth(0).unwrap_or(NO_CHAR) != RES_FORK_1 { return false; }
+    if  file_name.chars().next().unwrap_or(NO_CHAR) != RES_FORK_1 { return false; }
     if  file_name.chars().nth(1).unwrap_or(NO_CHAR) != RES_FORK_2 { return false; }
     true
 }
 
+// Determines if a file is an AppleDouble resource fork; one with a fork's
+// name (see is_resource_fork), and the AppleDouble header.
+fn is_apple_double(file: &Path) -> bool {
+    let mut magic = [0u8; 4];
+    is_resource_fork(&opt_osstr_to_string(file.file_name(), NO_FILE_NAME))
+        && fs::File::open(file).and_then(|mut f| io::Read::read_exact(&mut f, &mut magic)).is_ok()
+        && magic == APPLE_DOUBLE_MAGIC
+}
+
 // Purges, or moves (backs up) the specified file.
 fn purge_or_backup_file(
-    path: &PathBuf,
-    library_path: &PathBuf,
-    backup_path: &PathBuf,
+    path: &Path,
+    library_path: &Path,
+    backup_path: &Path,
     backup: bool,
     purge: bool
-) -> Result<PathBuf, PathBuf>
+) -> Result<PathBuf, PurgeError>
 {
     // If backup is enabled, backup the file first ...
     if backup && purge {
-        if backup_file(&path, library_path, backup_path).is_err() {
-            return Err(path.to_path_buf());
-        }
+        backup_file(path, library_path, backup_path)?;
+    }
+    // ... then purge the file as needed ...
+    if purge {
+        fs::remove_file(path)
+            .map_err(|source| PurgeError::Purge { path: path.to_path_buf(), source })?;
+    }
+    Ok(path.to_path_buf())
+}
+
+// Renames (or copies) a file to its new name, optionally backing up the
+// original first.  Nothing is changed unless "purge" is set.
+fn rename_or_copy_file(
+    rename: &ArtRename,
+    library_path: &Path,
+    backup_path: &Path,
+    backup: bool,
+    copy: bool,
+    purge: bool
+) -> Result<PathBuf, PurgeError>
+{
+    if !purge { return Ok(rename.to.clone()); }
+
+    let error = |source| PurgeError::Rename {
+        path: rename.from.clone(), target: rename.to.clone(), source
+    };
+    // The target may still exist if it could not be purged; a difference in
+    // case only may just be the file itself, on a case-insensitive system.
+    let same_name = rename.from.file_name().map(|n| n.to_ascii_lowercase())
+        == rename.to.file_name().map(|n| n.to_ascii_lowercase());
+    if rename.to.exists() && !same_name {
+        return Err(error(io::Error::from(io::ErrorKind::AlreadyExists)));
     }
-    // ... then purge the file as needed ...
-    if purge {
-        if fs::remove_file(&path).is_err() {
-            eprintln!("Could not purge: {}", path.display());
-            return Err(path.to_path_buf());
-        }
+
+    if copy {
+        fs::copy(&rename.from, &rename.to).map_err(error)?;
+    } else {
+        // A rename loses the original name, so back the original up first.
+        if backup { backup_file(&rename.from, library_path, backup_path)?; }
+        fs::rename(&rename.from, &rename.to).map_err(error)?;
     }
-    Ok(path.to_path_buf())
+    Ok(rename.to.clone())
 }
 
 // Backup the specified file, creating the target directory if needed.
 fn backup_file(
-    path: &PathBuf,
-    library_path: &PathBuf,
-    backup_path: &PathBuf
-) -> Result<PathBuf, PathBuf>
+    path: &Path,
+    library_path: &Path,
+    backup_path: &Path
+) -> Result<PathBuf, PurgeError>
 {
     // Get the path to copy this file TO.         
     let relative_source_path = 
@@ -404,20 +2381,19 @@ fn backup_file(
             
     // Create the target directory IF needed ...
     let target_dir = target_path.parent().unwrap();
-    if !target_dir.exists() { 
-        if fs::create_dir_all(target_dir).is_err() {
-            eprintln!("Could not create target directory: {}", target_dir.display());
-            return Err(target_dir.to_path_buf()); }
+    if !target_dir.exists() {
+        fs::create_dir_all(target_dir)
+            .map_err(|source| PurgeError::CreateDir { path: target_dir.to_path_buf(), source })?;
     }
                
     // We use copy here, instead of "move", as "move" can only target the
     // same volume that the source files resides on.
-    if fs::copy(&path, &target_path).is_ok() {
-        return Ok(target_path);
-    } else {
-        eprintln!("Could not backup: {} -> {}", path.display(), target_path.display());
-        return Err(target_path.to_path_buf());
-    }    
+    match fs::copy(path, &target_path) {
+        Ok(_) => Ok(target_path),
+        Err(source) => {
+            Err(PurgeError::Backup { path: path.to_path_buf(), target: target_path, source })
+        }
+    }
 }
 
 // Prints File Type List
@@ -438,6 +2414,11 @@ fn print_list( prefix: &str, arr: &[&str], keep: bool ) {
     println!("{}", list);
 }
 
+// Joins patterns into a comma-separated list, for display.
+fn join_patterns(patterns: &[Pattern]) -> String {
+    patterns.iter().map(|p| p.as_str()).collect::<Vec<&str>>().join(", ")
+}
+
 // Prints specified text IF in VERBOSE mode.
 fn print_verbose( text: String, verbose: bool) {
     if verbose { println!("{}", text); }
@@ -452,6 +2433,45 @@ fn opt_osstr_to_string(opt_osstr: Option<&OsStr>, default: &str) -> String {
     String::from(opt_osstr.unwrap_or(OsStr::new(default)).to_string_lossy())
 }
 
+// Formats a size in bytes as a human readable string (e.g. "1.5 MB").
+fn format_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= SIZE_UNIT_STEP && unit < SIZE_UNITS.len() - 1 {
+        size /= SIZE_UNIT_STEP;
+        unit += 1;
+    }
+    // Whole bytes don't need a decimal place.
+    if unit == 0 {
+        format!("{} {}", bytes, SIZE_UNITS[0])
+    } else {
+        format!("{:.1} {}", size, SIZE_UNITS[unit])
+    }
+}
+
+// Formats a size exactly, in the largest unit it is a whole number of (e.g.
+// "2MB", "1536B"); as it would be given on the command line.
+fn format_exact_size(bytes: u64) -> String {
+    let mut size = bytes;
+    let mut unit = 0;
+    while size >= 1024 && size.is_multiple_of(1024) && unit < SIZE_UNITS.len() - 1 {
+        size /= 1024;
+        unit += 1;
+    }
+    format!("{}{}", size, SIZE_UNITS[unit])
+}
+
+// Formats an age in its largest whole unit (e.g. "3d", "5h", "40s").
+fn format_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    match seconds {
+        s if s >= 86400 => format!("{}d", s / 86400),
+        s if s >= 3600 => format!("{}h", s / 3600),
+        s if s >= 60 => format!("{}m", s / 60),
+        s => format!("{}s", s),
+    }
+}
+
 // Unit Tests
 #[cfg(test)]
 mod tests {
@@ -461,7 +2481,7 @@ mod tests {
     #[test]
     fn build_keep_extensions_list_keep_music_only() { 
         // Keep music files, but not additional audio files or documentation ...
-        let keep_extensions = build_keep_extensions_list(false, false);
+        let keep_extensions = build_keep_extensions_list(false, false, false, false, false);
         // ... which should just be the pure MUSIC FILE TYPES extensions.
         assert_eq!(keep_extensions.len(), MUSIC_FILE_TYPES.len());
     }
@@ -469,7 +2489,7 @@ mod tests {
     #[test]
     fn build_keep_extensions_list_keep_music_and_audio() { 
         // Keep music files, and additional audio files but no documentation ...
-        let keep_extensions = build_keep_extensions_list(true, false);
+        let keep_extensions = build_keep_extensions_list(true, false, false, false, false);
         // ... which should just be the pure MUSIC + AUDIO extensions.
         assert_eq!(keep_extensions.len(), MUSIC_FILE_TYPES.len() + AUDIO_FILE_TYPES.len());
     }
@@ -477,7 +2497,7 @@ mod tests {
     #[test]
     fn build_keep_extensions_list_keep_music_and_documentation() { 
         // Keep music files, discard additional audio files but keep documentation ...
-        let keep_extensions = build_keep_extensions_list(false, true);
+        let keep_extensions = build_keep_extensions_list(false, true, false, false, false);
         // ... which should just be the pure MUSIC + DOCUMENT extensions.
         assert_eq!(
             keep_extensions.len(),
@@ -488,7 +2508,7 @@ mod tests {
     #[test]
     fn build_keep_extensions_list_keep_all() { 
         // Keep music files, additional audio files and documentation ...
-        let keep_extensions = build_keep_extensions_list(true, true);
+        let keep_extensions = build_keep_extensions_list(true, true, false, false, false);
         // ... which should  be the pure MUSIC + AUDIO + DOCUMENT extensions.
         assert_eq!(
             keep_extensions.len(),
@@ -496,34 +2516,463 @@ mod tests {
         );
     }
 
-    // Art File Inclusions/Exclusions
     #[test]
-    fn build_keep_art_file_list_keep_art() {
-        // We're not deleting art files ...
-        let keep_art_files = build_keep_art_file_list(false);
-        // ... so the file count should be the product of names and extensions.
-        assert_eq!(keep_art_files.len(), ALBUM_ART_FILENAMES.len() * ALBUM_ART_EXTENSIONS.len());
+    fn build_keep_extensions_list_keep_music_and_rip_metadata() {
+        // Keep music files and rip metadata, separately from documentation ...
+        let keep_extensions = build_keep_extensions_list(false, false, true, false, false);
+        // ... which should just be the pure MUSIC + RIP METADATA extensions,
+        // and the playlists a ripper writes.
+        assert_eq!(
+            keep_extensions.len(),
+            MUSIC_FILE_TYPES.len() + RIP_METADATA_FILE_TYPES.len() + RIP_PLAYLIST_FILE_TYPES.len()
+        );
+        assert!(keep_extensions.contains(&String::from("m3u8")));
+        assert!(!keep_extensions.contains(&String::from("m3u")));
+        assert!(!keep_extensions.contains(&String::from("pdf")));
+    }
+
+    #[test]
+    fn build_keep_extensions_list_keep_music_and_video() {
+        // Keep music files and video, which includes .wmv ...
+        let keep_extensions = build_keep_extensions_list(false, false, false, true, false);
+        // ... which should just be the pure MUSIC + VIDEO extensions.
+        assert_eq!(keep_extensions.len(), MUSIC_FILE_TYPES.len() + VIDEO_FILE_TYPES.len());
+        assert!(keep_extensions.contains(&String::from("wmv")));
+    }
+
+    // Art File Recognition
+    #[test]
+    fn art_matcher_default_names() {
+        let art = ArtMatcher::default();
+        // Common, ripper and Windows Media Player names, in any case ...
+        for name in [
+            "cover.jpg", "Folder.JPG", "front.webp", "folder.gif", "album.avif", "thumb.bmp",
+            "large_cover.tif", "Cover (Front).jpg", "folder [1].png", "AlbumArtSmall.jpg",
+            "AlbumArt_{4B1E5D3C-0A7F-4C2E-9D7B-123456789ABC}_Large.jpg",
+        ] {
+            assert!(art.is_art(name), "{} should be art", name);
+        }
+        // ... but not other images, or other file types with art names.
+        for name in ["scan01.jpg", "covers.jpg", "cover.pdf", "folder.txt", "albumart_x_large.jpg"] {
+            assert!(!art.is_art(name), "{} should not be art", name);
+        }
+    }
+
+    #[test]
+    fn art_matcher_additional_patterns() {
+        let art = ArtMatcher::new(
+            vec![parse_art_pattern(r"^scan\d+$").unwrap()],
+            vec![Pattern::new("*_cover.heic").unwrap()],
+            vec![String::from(".JP2")],
+        );
+        assert!(art.is_art("Scan01.jpg"));
+        assert!(art.is_art("cover.jp2"));
+        assert!(art.is_art("Album_Cover.HEIC"));
+        // The defaults still apply.
+        assert!(art.is_art("cover.jpg"));
+        assert!(!art.is_art("scan01.heic"));
+    }
+
+    // File Classification
+    #[test]
+    fn classify_extension_known_types() {
+        // Each type list maps to its own category, regardless of case ...
+        assert_eq!(classify_extension("flac"), FileCategory::Music);
+        assert_eq!(classify_extension("MP3"), FileCategory::Music);
+        assert_eq!(classify_extension("au"), FileCategory::Audio);
+        assert_eq!(classify_extension("pdf"), FileCategory::Document);
+        assert_eq!(classify_extension("cue"), FileCategory::RipMetadata);
+        assert_eq!(classify_extension("LRC"), FileCategory::Sidecar);
+        assert_eq!(classify_extension("wmv"), FileCategory::Video);
+        assert_eq!(classify_extension("jpg"), FileCategory::Art);
+    }
+
+    #[test]
+    fn type_lists_do_not_conflict() {
+        // No extension is in more than one category's type list ...
+        let art_extensions: Vec<String> =
+            ALBUM_ART_EXTENSIONS.iter().map(|e| String::from(*e)).collect();
+        assert_eq!(find_conflicting_types(&art_extensions), Vec::<String>::new());
+
+        // ... and additional art extensions can't claim another category's.
+        assert_eq!(
+            find_conflicting_types(&[String::from("jpg"), String::from("mp4")]),
+            vec!["\"mp4\" is video and art"]
+        );
+        assert_eq!(
+            find_conflicting_types(&[String::from("part")]),
+            vec!["\"part\" is partial download and art"]
+        );
+    }
+
+    #[test]
+    fn classify_extension_unknown_types() {
+        // ... and anything not on a list is unknown.
+        assert_eq!(classify_extension("xyz"), FileCategory::Unknown);
+        assert_eq!(classify_extension(""), FileCategory::Unknown);
+    }
+
+    #[test]
+    fn build_inventory_counts_each_extension() {
+        setup_test_files();
+        let inventory = build_inventory(&get_test_library_paths());
+        // One file of each of the four test extensions.
+        assert_eq!(inventory.len(), 4);
+        for extension in ["mp3", "au", "txt", "jpg"] {
+            assert_eq!(inventory[extension].file_count, 1);
+        }
+    }
+
+    #[test]
+    fn format_size_units() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0 GB");
+    }
+
+    // Command Line
+    #[test]
+    fn args_are_valid() {
+        // Catches clap configuration errors (e.g. unknown argument ids).
+        use clap::CommandFactory;
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn args_art_mode() {
+        let args = |args: &[&str]| Args::try_parse_from(args).map(|a| (a.art, a.art_mode));
+        assert_eq!(args(&["mlcp", "music"]).unwrap(), (false, None));
+        // -a is a flag, as it always has been, wherever it is ...
+        assert_eq!(args(&["mlcp", "-a", "music"]).unwrap(), (true, None));
+        assert_eq!(args(&["mlcp", "music", "-a"]).unwrap(), (true, None));
+        // ... and the modes are a separate option.
+        assert_eq!(
+            args(&["mlcp", "music", "--art-mode", "one"]).unwrap(), (false, Some(ArtMode::One))
+        );
+        assert_eq!(
+            args(&["mlcp", "--art-mode=if-embedded", "music"]).unwrap(),
+            (false, Some(ArtMode::IfEmbedded))
+        );
+        assert!(args(&["mlcp", "-a", "--art-mode", "one", "music"]).is_err());
+    }
+
+    #[test]
+    fn args_art_extract() {
+        let args = Args::try_parse_from([
+            "mlcp", "art", "extract", "music", "-p", "--art-pattern", "^scan$"
+        ]).unwrap();
+        // The art options apply to the command too.
+        assert_eq!(args.art_pattern.len(), 1);
+        match args.command {
+            Some(Command::Art { command: ArtCommand::Extract { library_path, name, purge, .. } }) => {
+                assert_eq!(library_path, "music");
+                assert_eq!(name, EXTRACTED_ART_NAME);
+                assert!(purge);
+            }
+            command => panic!("unexpected command: {:?}", command),
+        }
+    }
+
+    // Exit Codes and Safety
+    #[test]
+    fn exit_code_by_outcome() {
+        assert_eq!(exit_code(0, 0), NOTHING_TO_DO);
+        assert_eq!(exit_code(0, 5), SUCCESS);
+        assert_eq!(exit_code(2, 5), PARTIAL_FAILURE);
+        assert_eq!(exit_code(5, 5), TOTAL_FAILURE);
+        // Large error counts must never wrap around to success.
+        assert_eq!(exit_code(256, 256), TOTAL_FAILURE);
+    }
+
+    #[test]
+    fn check_safety_backup_inside_library() {
+        setup_test_files();
+        // Backing up into the library (or a sub-folder of it) is refused ...
+        assert!(check_safety("tests/library", "tests/library", true).is_some());
+        assert!(check_safety("tests", "tests/library", true).is_some());
+        // ... but a sibling folder is fine, as is no backup at all.
+        assert!(check_safety("tests/library", "tests", true).is_none());
+        assert!(check_safety("tests/library", "", false).is_none());
     }
 
     #[test]
-    fn build_keep_art_file_list_discard_art() {
-        // We're deleting art files ...
-        let keep_art_files = build_keep_art_file_list(true);
-        // ... so the "keep list" should be empty.
-        assert_eq!(keep_art_files.len(), 0);   
+    fn check_safety_filesystem_root() {
+        assert!(check_safety("/", "", false).is_some());
     }
 
     // File Forms
     #[test]
     fn is_resource_fork_is_resource() {
         // Resource Forks start with "._"
-        assert!(is_resource_fork(&"._ResourceFork"));
+        assert!(is_resource_fork("._ResourceFork"));
     }
 
     #[test]
     fn is_resource_fork_is_not_resource() {
         // Resource Forks start with "._"
-        assert!(!is_resource_fork(&"NotResourceFork"));
+        assert!(!is_resource_fork("NotResourceFork"));
+    }
+
+    #[test]
+    fn build_purge_file_list_resource_forks() {
+        let fork = "\0\x05\x16\x07 AppleDouble";
+        let library = setup_test_library("resource_forks", &[
+            ("Album/01.mp3", ""),
+            ("Album/._01.mp3", fork),
+            ("Album/notes.txt", ""),
+            ("Album/._notes.txt", fork),
+            ("Album/._gone.mp3", fork),
+        ]);
+        let library_paths = get_library_paths(library.to_str().unwrap());
+        let mut options = test_options(false, false, false);
+        let plan = build_purge_file_list(library_paths.clone(), &options);
+
+        // Forks go with their purged file (just before it), or if it is missing.
+        assert_eq!(plan.purge_files, vec![
+            library.join("Album/._notes.txt"),
+            library.join("Album/notes.txt"),
+            library.join("Album/._gone.mp3"),
+        ]);
+        assert_eq!(
+            plan.purge_reasons[&library.join("Album/._gone.mp3")],
+            "AppleDouble file for missing gone.mp3"
+        );
+
+        // All forks can be purged, e.g. from a DAP's card.
+        options.purge_apple_double = true;
+        let plan = build_purge_file_list(library_paths, &options);
+        assert_eq!(plan.purge_files.len(), 4);
+        assert!(list_contains_file(&plan.purge_files, "Album/._01.mp3"));
+    }
+
+    #[test]
+    fn build_purge_file_list_system_junk() {
+        let library = setup_test_library("system_junk", &[
+            ("Album/01.mp3", ""),
+            ("Album/booklet.pdf", ""),
+            ("Album/.DS_Store", ""),
+            ("Album/thumbs.db", ""),
+            ("Album/Desktop.ini", ""),
+            ("Kept/.mlcp.toml", "junk = true\n"),
+            ("Kept/desktop.ini", ""),
+        ]);
+        let library_paths = get_library_paths(library.to_str().unwrap());
+        let mut options = test_options(false, true, true);
+        options.folder_configs = load_folder_configs(&library_paths).unwrap();
+        let plan = build_purge_file_list(library_paths, &options);
+
+        // Junk is purged even though everything else is kept, unless a
+        // folder keeps it.
+        assert_eq!(plan.purge_files.len(), 3);
+        assert!(list_contains_file(&plan.purge_files, "Album/.DS_Store"));
+        assert!(list_contains_file(&plan.purge_files, "Album/thumbs.db"));
+        assert!(list_contains_file(&plan.purge_files, "Album/Desktop.ini"));
+        assert!(plan.purge_files.iter().all(|file| plan.purge_reasons[file] == "system junk"));
+    }
+
+    #[test]
+    fn build_purge_file_list_rip_metadata() {
+        let library = setup_test_library("rip_metadata", &[
+            ("Album/01.flac", ""),
+            ("Album/album.cue", ""),
+            ("Album/album.log", ""),
+            ("Album/album.m3u8", "01.flac\n"),
+            ("Album/notes.txt", ""),
+            ("Archive/.mlcp.toml", "rip_metadata = true\n"),
+            ("Archive/01.flac", ""),
+            ("Archive/album.accurip", ""),
+            ("Archive/album.m3u8", "01.flac\n"),
+        ]);
+        let library_paths = get_library_paths(library.to_str().unwrap());
+        let mut options = test_options(false, false, true);
+        options.folder_configs = load_folder_configs(&library_paths).unwrap();
+        let plan = build_purge_file_list(library_paths, &options);
+
+        // Keeping documents does not keep rip metadata, unless a folder does.
+        assert_eq!(plan.purge_files.len(), 3);
+        assert!(list_contains_file(&plan.purge_files, "Album/album.cue"));
+        assert!(list_contains_file(&plan.purge_files, "Album/album.log"));
+        assert!(list_contains_file(&plan.purge_files, "Album/album.m3u8"));
+
+        // With --keep-rip-metadata, it is all kept; including the rip's .m3u8,
+        // which is otherwise purged as a playlist.
+        options.policy.keep_rip_metadata = true;
+        let plan = build_purge_file_list(get_library_paths(library.to_str().unwrap()), &options);
+        assert!(plan.purge_files.is_empty());
+    }
+
+    #[test]
+    fn build_purge_file_list_verify_checksums() {
+        let library = setup_test_library("verify_checksums", &[
+            ("Good/01.mp3", "The quick brown fox jumps over the lazy dog"),
+            ("Good/album.sfv", "01.mp3 414FA339\n"),
+            ("Good/album.md5", "9e107d9d372bb6826bd81d3542a419d6 *01.mp3\n"),
+            ("Bad/01.mp3", "The quick brown fox jumps over the lazy cat"),
+            ("Bad/album.sfv", "01.mp3 414FA339\n"),
+            ("Bad/album.log", ""),
+        ]);
+        let library_paths = get_library_paths(library.to_str().unwrap());
+        let mut options = test_options(false, false, false);
+        options.verify_checksums = true;
+        let plan = build_purge_file_list(library_paths, &options);
+
+        // Only the checksum files of the folder that verified are purged; the
+        // failure is reported, and other rip metadata is purged regardless.
+        assert_eq!(plan.purge_files.len(), 3);
+        assert!(list_contains_file(&plan.purge_files, "Good/album.sfv"));
+        assert!(list_contains_file(&plan.purge_files, "Good/album.md5"));
+        assert!(list_contains_file(&plan.purge_files, "Bad/album.log"));
+        assert_eq!(plan.protected_files.len(), 1);
+        assert!(plan.protected_files[0].path.ends_with("Bad/album.sfv"));
+        assert_eq!(plan.verified_folders, vec![library.join("Good")]);
+        assert_eq!(plan.warnings.len(), 1);
+        assert!(plan.warnings[0].contains("CRC32 mismatch, in album.sfv"));
+    }
+
+    #[test]
+    fn build_purge_file_list_sidecars() {
+        let library = setup_test_library("sidecars", &[
+            ("Album/01 - Intro.flac", ""),
+            ("Album/01 - Intro.lrc", ""),
+            ("Album/02 - Outro.MP3", ""),
+            ("Album/02 - outro.json", ""),
+            ("Album/03 - Hidden.lrc", ""),
+            ("Album/00-artist-album-2003-grp.nfo", ""),
+            ("Album/04 - Skit.au", ""),
+            ("Album/04 - Skit.lrc", ""),
+        ]);
+        let library_paths = get_library_paths(library.to_str().unwrap());
+        let plan = build_purge_file_list(library_paths.clone(), &test_options(false, true, true));
+
+        // Sidecars are kept alongside their music file, whatever the case.
+        assert_eq!(plan.purge_files.len(), 2);
+        assert!(list_contains_file(&plan.purge_files, "Album/03 - Hidden.lrc"));
+        assert!(list_contains_file(&plan.purge_files, "Album/00-artist-album-2003-grp.nfo"));
+        assert!(plan.purge_files.iter()
+            .all(|file| plan.purge_reasons[file] == "no kept music file of the same name"));
+
+        // ... but not alongside music that is itself purged, by type or by rule.
+        let mut options = test_options(false, false, true);
+        options.rules = vec!["purge if name == \"01 - Intro.flac\"".parse().unwrap()];
+        let plan = build_purge_file_list(library_paths, &options);
+        assert_eq!(plan.purge_files.len(), 6);
+        assert!(list_contains_file(&plan.purge_files, "Album/01 - Intro.lrc"));
+        assert!(list_contains_file(&plan.purge_files, "Album/04 - Skit.lrc"));
+        assert!(!list_contains_file(&plan.purge_files, "Album/02 - outro.json"));
+    }
+
+    #[test]
+    fn build_purge_file_list_playlist_references() {
+        let _ = fs::remove_dir_all("tests/libraries/playlist_references");
+        let library = setup_test_library("playlist_references", &[
+            ("Album/00 - Intro.opus", ""),
+            ("Album/01 - Song.flac", ""),
+            ("Album/album.m3u8", "#EXTM3U\n00 - Intro.opus\n01 - Song.flac\n02 - Gone.flac\n"),
+            ("Album/notes.txt", ""),
+            ("Mixes/road trip.pls", "[playlist]\nFile1=../Album/notes.txt\n"),
+        ]);
+        let library_paths = get_library_paths(library.to_str().unwrap());
+        let mut options = test_options(false, false, false);
+        let plan = build_purge_file_list(library_paths, &options);
+
+        // Referenced files are protected, whatever their type, but playlists
+        // themselves are purged unless kept; missing entries are reported.
+        assert_eq!(plan.purge_files.len(), 2);
+        assert!(list_contains_file(&plan.purge_files, "Album/album.m3u8"));
+        assert!(list_contains_file(&plan.purge_files, "Mixes/road trip.pls"));
+        assert_eq!(plan.protected_files.len(), 2);
+        assert!(plan.protected_files.iter().any(|p| p.path.ends_with("00 - Intro.opus")
+            && p.reason == "referenced by playlist album.m3u8"));
+        assert!(plan.protected_files.iter().any(|p| p.path.ends_with("Album/notes.txt")));
+        assert_eq!(plan.warnings.len(), 1);
+        assert!(plan.warnings[0].contains("02 - Gone.flac (in album.m3u8)"));
+
+        options.policy.keep_playlists = true;
+        let plan = build_purge_file_list(get_library_paths(library.to_str().unwrap()), &options);
+        assert!(plan.purge_files.is_empty());
+
+        // Playlists outside the scope, or protected by an ignore file, stay in
+        // the library, so still protect what they reference.
+        options.policy.keep_playlists = false;
+        options.library_root = library.clone();
+        options.exclude = vec![Pattern::new("Mixes/**").unwrap()];
+        fs::write(library.join("Album/.mlcpignore"), "album.m3u8\n").unwrap();
+        let plan = build_purge_file_list(get_library_paths(library.to_str().unwrap()), &options);
+        assert!(plan.purge_files.is_empty());
+        assert!(plan.protected_files.iter().any(|p| p.path.ends_with("00 - Intro.opus")
+            && p.reason == "referenced by playlist album.m3u8"));
+        assert!(plan.protected_files.iter().any(|p| p.path.ends_with("Album/notes.txt")
+            && p.reason == "referenced by playlist road trip.pls"));
+    }
+
+    #[test]
+    fn build_purge_file_list_playlist_absolute_entries() {
+        let library = setup_test_library("playlist_absolute", &[
+            ("Album/00 - Intro.opus", ""),
+            ("Album/01 - Song.flac", ""),
+        ]);
+        let intro = library.join("Album/00 - Intro.opus");
+        fs::write(library.join("Album/abs.m3u"), format!("{}\n", intro.display())).unwrap();
+        let xspf = format!("<location>file://{}</location>", intro.display());
+        fs::write(library.join("Album/abs.xspf"), xspf).unwrap();
+
+        // Absolute entries match the paths of a library given as a relative path.
+        let library_path = "tests/libraries/playlist_absolute";
+        let mut options = test_options(false, false, false);
+        options.library_root = get_library_root(library_path);
+        options.policy.keep_playlists = true;
+        let plan = build_purge_file_list(get_library_paths(library_path), &options);
+        assert!(plan.purge_files.is_empty());
+        assert_eq!(plan.protected_files.len(), 1);
+        assert!(plan.warnings.is_empty());
+    }
+
+    #[test]
+    fn build_purge_file_list_partial_downloads() {
+        let library = setup_test_library("partial_downloads", &[
+            ("Album/01.mp3", ""),
+            ("Album/02.mp3.part", ""),
+            ("Album/03.mp3.crdownload", ""),
+            ("Album/~$booklet.docx", ""),
+        ]);
+        let old = SystemTime::now() - Duration::from_secs(2 * 3600);
+        for path in ["Album/03.mp3.crdownload", "Album/~$booklet.docx"] {
+            fs::File::options().write(true).open(library.join(path)).unwrap()
+                .set_modified(old).unwrap();
+        }
+        let library_paths = get_library_paths(library.to_str().unwrap());
+        let mut options = test_options(false, false, false);
+        options.download_grace = Duration::from_secs(30 * 60);
+        let plan = build_purge_file_list(library_paths.clone(), &options);
+
+        // The download that may still be in progress is spared.
+        assert_eq!(plan.purge_files.len(), 2);
+        assert!(list_contains_file(&plan.purge_files, "Album/03.mp3.crdownload"));
+        assert!(list_contains_file(&plan.purge_files, "Album/~$booklet.docx"));
+        assert!(plan.purge_files.iter().all(|file| plan.purge_reasons[file] == "partial download"));
+        assert!(plan.protected_files[0].path.ends_with("Album/02.mp3.part"));
+        assert!(plan.protected_files[0].reason.contains("within --download-grace 30m"));
+
+        // Without a grace period, everything goes.
+        options.download_grace = Duration::ZERO;
+        assert_eq!(build_purge_file_list(library_paths, &options).purge_files.len(), 3);
+    }
+
+    #[test]
+    fn build_purge_file_list_fork_names_without_header() {
+        let library = setup_test_library("fork_names", &[
+            ("Album/._intro.mp3", "ID3"),
+            ("Album/._notes.txt", "notes"),
+        ]);
+        let mut options = test_options(false, false, false);
+        options.purge_apple_double = true;
+        let plan = build_purge_file_list(get_library_paths(library.to_str().unwrap()), &options);
+
+        // Without the AppleDouble header, these are just oddly named files.
+        assert_eq!(plan.purge_files, vec![library.join("Album/._notes.txt")]);
+        assert!(plan.purge_reasons.is_empty());
+        assert!(!is_apple_double(&library.join("Album/._intro.mp3")));
     }
 
     #[test]
@@ -538,48 +2987,48 @@ mod tests {
     fn get_actual_extensions_keep_audio_and_docs() {
         setup_test_files();
         let extensions = get_actual_extensions(
-            &get_test_library_paths(), true, true
+            &get_test_library_paths(), true, true, false, false, false
         );
         // We should get .au, .txt and .mp3 back; so three extensions
         assert_eq!(extensions.len(), 3);
-        assert_eq!(extensions.contains(&String::from("mp3")), true);
-        assert_eq!(extensions.contains(&String::from("au")), true);
-        assert_eq!(extensions.contains(&String::from("txt")), true);
+        assert!(extensions.contains(&String::from("mp3")));
+        assert!(extensions.contains(&String::from("au")));
+        assert!(extensions.contains(&String::from("txt")));
     }
 
     #[test]
     fn get_actual_extensions_keep_audio_discard_docs() {
         setup_test_files();
         let extensions = get_actual_extensions(
-            &get_test_library_paths(), true, false
+            &get_test_library_paths(), true, false, false, false, false
         );
         // We should get .au and .mp3 back; so two extensions
         assert_eq!(extensions.len(), 2);        
-        assert_eq!(extensions.contains(&String::from("mp3")), true);
-        assert_eq!(extensions.contains(&String::from("au")), true);        
+        assert!(extensions.contains(&String::from("mp3")));
+        assert!(extensions.contains(&String::from("au")));        
     }
 
     #[test]
     fn get_actual_extensions_discard_audio_keep_docs() {
         setup_test_files();
         let extensions = get_actual_extensions(
-            &get_test_library_paths(), false, true
+            &get_test_library_paths(), false, true, false, false, false
         );
         // We should get .txt and .mp3 back; so two extensions
         assert_eq!(extensions.len(), 2);
-        assert_eq!(extensions.contains(&String::from("mp3")), true);
-        assert_eq!(extensions.contains(&String::from("txt")), true);
+        assert!(extensions.contains(&String::from("mp3")));
+        assert!(extensions.contains(&String::from("txt")));
     }
 
     #[test]
     fn get_actual_extensions_discard_audio_and_docs() {
         setup_test_files();
         let extensions = get_actual_extensions(
-            &get_test_library_paths(), false, false
+            &get_test_library_paths(), false, false, false, false, false
         );
         // We should just get .mp3 back; so one extension
         assert_eq!(extensions.len(), 1);
-        assert_eq!(extensions.contains(&String::from("mp3")), true);
+        assert!(extensions.contains(&String::from("mp3")));
     }
 
     #[test]
@@ -587,10 +3036,8 @@ mod tests {
         setup_test_files();
         let file_list = build_purge_file_list(
             get_test_library_paths(),
-            false,
-            true,
-            true
-        );
+            &test_options(false, true, true)
+        ).purge_files;
         // No files should be purged.
         assert_eq!(file_list.len(), 0);        
     }
@@ -600,10 +3047,8 @@ mod tests {
         setup_test_files();
         let file_list = build_purge_file_list(
             get_test_library_paths(),
-            true,
-            false,
-            false
-        );
+            &test_options(true, false, false)
+        ).purge_files;
         // Three files should be purged (album.jpg, audio.au, doc.txt).
         assert_eq!(file_list.len(), 3);
         assert!(list_contains_file(&file_list, "album.jpg"));
@@ -616,10 +3061,8 @@ mod tests {
         setup_test_files();
         let file_list = build_purge_file_list(
             get_test_library_paths(),
-            true,
-            true,
-            false
-        );
+            &test_options(true, true, false)
+        ).purge_files;
         // Two files should be purged (album.jpg, doc.txt).
         assert_eq!(file_list.len(), 2);
         assert!(list_contains_file(&file_list, "album.jpg"));
@@ -631,10 +3074,8 @@ mod tests {
         setup_test_files();
         let file_list = build_purge_file_list(
             get_test_library_paths(),
-            false,
-            false,
-            false
-        );
+            &test_options(false, false, false)
+        ).purge_files;
         // Two files should be purged (audio.au and doc.txt).
         assert_eq!(file_list.len(), 2);
         assert!(list_contains_file(&file_list, "audio.au"));
@@ -646,16 +3087,613 @@ mod tests {
         setup_test_files();
         let file_list = build_purge_file_list(
             get_test_library_paths(),
-            true,
-            false,
-            true
-        );
+            &test_options(true, false, true)
+        ).purge_files;
         // Two files should be purged (audio.au and album.jpg).
         assert_eq!(file_list.len(), 2);
         assert!(list_contains_file(&file_list, "audio.au"));
         assert!(list_contains_file(&file_list, "album.jpg"));        
     }
 
+    #[test]
+    fn build_purge_file_list_ignore_files() {
+        let library = setup_test_library("ignore", &[
+            (".mlcpignore", "liner_notes.html\nBonus/\n"),
+            ("music.mp3", ""),
+            ("liner_notes.html", ""),
+            ("other.html", ""),
+            ("Bonus/video.mkv", ""),
+            ("Sub/.mlcpignore", "!liner_notes.html\n"),
+            ("Sub/liner_notes.html", ""),
+        ]);
+        let plan = build_purge_file_list(
+            get_library_paths(library.to_str().unwrap()), &test_options(false, false, false)
+        );
+
+        // The top-level notes and Bonus sub-folder are protected ...
+        assert_eq!(plan.protected_files.len(), 2);
+        assert!(plan.protected_files.iter().any(|p| p.path.ends_with("liner_notes.html")));
+        assert!(plan.protected_files.iter().any(|p| p.path.ends_with("Bonus/video.mkv")));
+        // ... but the Sub folder's own ignore file un-protects its notes, and
+        // the ignore files themselves are never purged.
+        assert_eq!(plan.purge_files.len(), 2);
+        assert!(list_contains_file(&plan.purge_files, "other.html"));
+        assert!(list_contains_file(&plan.purge_files, "Sub/liner_notes.html"));
+    }
+
+    #[test]
+    fn build_purge_file_list_include_and_exclude() {
+        let library = setup_test_library("scope", &[
+            ("Rock/Album/notes.txt", ""),
+            ("Soundtracks/Film/notes.txt", ""),
+            ("Soundtracks/Film/Extras/notes.txt", ""),
+            ("Classical/Opera/notes.txt", ""),
+        ]);
+        let mut options = test_options(false, false, false);
+        options.library_root = library.clone();
+        options.include = vec![Pattern::new("Soundtracks/**").unwrap()];
+        options.exclude = vec![Pattern::new("*/*/Extras/**").unwrap()];
+        let file_list = build_purge_file_list(
+            get_library_paths(library.to_str().unwrap()), &options
+        ).purge_files;
+
+        // Only the included, and not excluded, file is purged.
+        assert_eq!(file_list.len(), 1);
+        assert!(list_contains_file(&file_list, "Soundtracks/Film/notes.txt"));
+    }
+
+    #[test]
+    fn build_purge_file_list_dot_relative_root() {
+        let _ = setup_test_library("dot_root", &[
+            ("Album/notes.txt", ""),
+            ("Extras/notes.txt", ""),
+        ]);
+        // A root given as "./..." still matches the (relative) library paths.
+        let library_path = "./tests/libraries/dot_root";
+        let mut options = test_options(false, false, false);
+        options.library_root = get_library_root(library_path);
+        options.exclude = vec![Pattern::new("Album/**").unwrap()];
+        options.rules = vec!["keep if path == Extras/notes.txt".parse().unwrap()];
+        let plan = build_purge_file_list(get_library_paths(library_path), &options);
+
+        assert!(plan.purge_files.is_empty());
+        assert_eq!(plan.protected_files.len(), 1);
+        assert_eq!(plan.no_music_folders, vec![
+            PathBuf::from("tests/libraries/dot_root/Album"),
+            PathBuf::from("tests/libraries/dot_root/Extras"),
+        ]);
+    }
+
+    #[test]
+    fn is_in_scope_single_star_stays_in_folder() {
+        let mut options = test_options(false, false, false);
+        options.library_root = PathBuf::from("lib");
+        options.include = vec![Pattern::new("*/Soundtracks/**").unwrap()];
+        // "*" matches exactly one folder level.
+        assert!(is_in_scope(Path::new("lib/Various/Soundtracks/a.txt"), &options));
+        assert!(!is_in_scope(Path::new("lib/Soundtracks/a.txt"), &options));
+        assert!(!is_in_scope(Path::new("lib/A/B/Soundtracks/a.txt"), &options));
+    }
+
+    #[test]
+    fn build_purge_file_list_folder_configs() {
+        let library = setup_test_library("policy", &[
+            ("Music/Album/booklet.pdf", ""),
+            ("Music/Album/intro.au", ""),
+            ("Audiobooks/.mlcp.toml", "other_audio = true\ndocuments = true\n"),
+            ("Audiobooks/Book/chapter.au", ""),
+            ("Audiobooks/Book/notes.pdf", ""),
+            ("Audiobooks/Abridged/.mlcp.toml", "documents = false\n"),
+            ("Audiobooks/Abridged/chapter.au", ""),
+            ("Audiobooks/Abridged/notes.pdf", ""),
+            ("Audiobooks/Series/.mlcp.toml", "documents = false\n"),
+            ("Audiobooks/Series/Disc 1/.mlcp.toml", "documents = true\n"),
+            ("Audiobooks/Series/Disc 1/chapter.au", ""),
+            ("Audiobooks/Series/Disc 1/notes.pdf", ""),
+        ]);
+        let library_paths = get_library_paths(library.to_str().unwrap());
+        let mut options = test_options(false, false, false);
+        options.folder_configs = load_folder_configs(&library_paths).unwrap();
+        let file_list = build_purge_file_list(library_paths, &options).purge_files;
+
+        // Music keeps the command line policy; Audiobooks keeps audio and
+        // documents, except Abridged, which inherits audio but purges documents;
+        // a disc follows its album's policy, whatever its own.
+        assert_eq!(file_list.len(), 4);
+        assert!(list_contains_file(&file_list, "Music/Album/booklet.pdf"));
+        assert!(list_contains_file(&file_list, "Music/Album/intro.au"));
+        assert!(list_contains_file(&file_list, "Audiobooks/Abridged/notes.pdf"));
+        assert!(list_contains_file(&file_list, "Audiobooks/Series/Disc 1/notes.pdf"));
+    }
+
+    #[test]
+    fn build_purge_file_list_rules() {
+        let library = setup_test_library("rules", &[
+            ("Album/music.mp3", ""),
+            ("Album/cover.jpg", "large cover art"),
+            ("Album/back.jpg", "large back art"),
+            ("Album/small.jpg", ""),
+            ("Album/booklet.pdf", ""),
+            ("Live/.mlcp.toml", "rules = [\"keep if ext == pdf\"]\n"),
+            ("Live/booklet.pdf", ""),
+        ]);
+        let library_paths = get_library_paths(library.to_str().unwrap());
+        let mut options = test_options(true, false, false);
+        options.library_root = library.clone();
+        options.folder_configs = load_folder_configs(&library_paths).unwrap();
+        options.rules = vec![
+            "keep if ext == jpg and size < 1 and not name == cover.jpg".parse().unwrap(),
+            "purge if ext == mp3 and size > 100".parse().unwrap(),
+        ];
+        let plan = build_purge_file_list(library_paths, &options);
+
+        // Non-empty art is purged (as --art was given), and the empty art is
+        // kept by the first rule; the second rule doesn't match the music.
+        assert_eq!(plan.purge_files.len(), 3);
+        assert!(list_contains_file(&plan.purge_files, "Album/cover.jpg"));
+        assert!(list_contains_file(&plan.purge_files, "Album/back.jpg"));
+        assert!(list_contains_file(&plan.purge_files, "Album/booklet.pdf"));
+        // The folder's own rule keeps its booklet, and says so.
+        let kept: Vec<&ProtectedFile> = plan.protected_files.iter()
+            .filter(|p| p.path.ends_with("Live/booklet.pdf")).collect();
+        assert_eq!(kept.len(), 1);
+        assert!(kept[0].reason.starts_with("rule \"keep if ext == pdf\" from "));
+    }
+
+    #[test]
+    fn build_purge_file_list_rule_reason() {
+        let library = setup_test_library("rules_reason", &[("Album/music.mp3", "")]);
+        let mut options = test_options(false, false, false);
+        options.rules = vec!["purge if name ~ \"^music\"".parse().unwrap()];
+        let plan = build_purge_file_list(get_library_paths(library.to_str().unwrap()), &options);
+
+        // Rules may purge any file type, and record why.
+        assert_eq!(plan.purge_files.len(), 1);
+        assert_eq!(
+            plan.purge_reasons[&plan.purge_files[0]],
+            "rule \"purge if name ~ \"^music\"\" from --rule"
+        );
+    }
+
+    #[test]
+    fn build_purge_file_list_size_limits() {
+        let library = setup_test_library("sizes", &[
+            ("Album/music.mp3", "some music"),
+            ("Album/folder.jpg", "normal art"),
+            ("Album/cover.jpg", "very large art, e.g. a 30MB TIFF scan"),
+            ("Album/thumb.jpg", ""),
+            ("Album/booklet.pdf", "a booklet"),
+        ]);
+        let mut options = test_options(false, false, true);
+        options.min_sizes = vec!["art=1".parse().unwrap()];
+        options.max_sizes = vec![
+            "art=20".parse().unwrap(),
+            "document=5".parse().unwrap(),
+        ];
+        let plan = build_purge_file_list(get_library_paths(library.to_str().unwrap()), &options);
+
+        // Oversized and empty art, and the oversized booklet, are purged; the
+        // normal art (and music, which has no limits) is kept.
+        assert_eq!(plan.purge_files.len(), 3);
+        assert!(list_contains_file(&plan.purge_files, "Album/cover.jpg"));
+        assert!(list_contains_file(&plan.purge_files, "Album/thumb.jpg"));
+        assert!(list_contains_file(&plan.purge_files, "Album/booklet.pdf"));
+        assert!(plan.purge_reasons[&library.join("Album/cover.jpg")].contains("larger than"));
+        assert!(plan.purge_reasons[&library.join("Album/thumb.jpg")].contains("smaller than"));
+    }
+
+    #[test]
+    fn build_purge_file_list_time_limits() {
+        let library = setup_test_library("times", &[
+            ("Album/music.mp3", ""),
+            ("Album/old.txt", ""),
+            ("Album/new.txt", ""),
+        ]);
+        let old = SystemTime::now() - Duration::from_secs(40 * 86400);
+        fs::File::options().write(true).open(library.join("Album/old.txt")).unwrap()
+            .set_modified(old).unwrap();
+        let library_paths = get_library_paths(library.to_str().unwrap());
+
+        // A grace period spares the new file ...
+        let mut options = test_options(false, false, false);
+        options.older_than = Some("30d".parse().unwrap());
+        let plan = build_purge_file_list(library_paths.clone(), &options);
+        assert_eq!(plan.purge_files.len(), 1);
+        assert!(list_contains_file(&plan.purge_files, "Album/old.txt"));
+        assert!(plan.protected_files[0].reason.contains("not --older-than 30d"));
+
+        // ... and a start date spares the old one.
+        let mut options = test_options(false, false, false);
+        options.newer_than = Some(
+            TimeLimit { text: String::from("39d"), time: old + Duration::from_secs(86400) }
+        );
+        let plan = build_purge_file_list(library_paths, &options);
+        assert_eq!(plan.purge_files.len(), 1);
+        assert!(list_contains_file(&plan.purge_files, "Album/new.txt"));
+    }
+
+    #[test]
+    fn build_purge_file_list_art_one_by_name() {
+        let library = setup_test_library("art_one_name", &[
+            ("Album/music.mp3", ""),
+            ("Album/AlbumArtSmall.jpg", ""),
+            ("Album/Folder.jpg", "folder art"),
+            ("Album/cover.jpg", ""),
+            ("Album/back.jpg", ""),
+            ("Single/music.mp3", ""),
+            ("Single/front.png", ""),
+        ]);
+        let library_paths = get_library_paths(library.to_str().unwrap());
+        let mut options = test_options(false, false, false);
+        options.policy.art = ArtMode::One;
+        let plan = build_purge_file_list(library_paths.clone(), &options);
+
+        // "cover" is preferred over the other art, whatever its size; a
+        // folder's only art file is always kept.
+        assert_eq!(plan.purge_files.len(), 3);
+        assert!(list_contains_file(&plan.purge_files, "Album/AlbumArtSmall.jpg"));
+        assert!(list_contains_file(&plan.purge_files, "Album/Folder.jpg"));
+        assert!(list_contains_file(&plan.purge_files, "Album/back.jpg"));
+        assert_eq!(
+            plan.purge_reasons[&library.join("Album/Folder.jpg")], "--art-mode one keeps cover.jpg"
+        );
+
+        // The priority is configurable.
+        options.art_priority = vec![String::from("folder")];
+        let plan = build_purge_file_list(library_paths, &options);
+        assert!(list_contains_file(&plan.purge_files, "Album/cover.jpg"));
+        assert!(!list_contains_file(&plan.purge_files, "Album/Folder.jpg"));
+    }
+
+    #[test]
+    fn build_purge_file_list_art_one_by_resolution() {
+        let library = setup_test_library("art_one_resolution", &[
+            ("Album/music.mp3", ""),
+            ("Album/Cover (Front).png", ""),
+            ("Album/front [1].png", ""),
+            ("Album/large_cover.jpg", "not really an image, but a large file"),
+        ]);
+        fs::write(library.join("Album/Cover (Front).png"), png_header(600, 600)).unwrap();
+        fs::write(library.join("Album/front [1].png"), png_header(1200, 1200)).unwrap();
+        let mut options = test_options(false, false, false);
+        options.policy.art = ArtMode::One;
+        let plan = build_purge_file_list(get_library_paths(library.to_str().unwrap()), &options);
+
+        // With no preferred names, the highest resolution wins over the
+        // largest (unreadable) file.
+        assert_eq!(plan.purge_files.len(), 2);
+        assert!(list_contains_file(&plan.purge_files, "Album/Cover (Front).png"));
+        assert!(list_contains_file(&plan.purge_files, "Album/large_cover.jpg"));
+    }
+
+    #[test]
+    fn build_purge_file_list_art_one_within_limits() {
+        let library = setup_test_library("art_one_limits", &[
+            ("Album/music.mp3", ""),
+            ("Album/cover.tif", "a huge scan of the cover, from a vinyl rip"),
+            ("Album/folder.jpg", "small art"),
+            ("Album/front.jpg", "small art"),
+            ("Ruled/music.mp3", ""),
+            ("Ruled/cover.jpg", ""),
+            ("Ruled/folder.jpg", ""),
+        ]);
+        let mut options = test_options(false, false, false);
+        options.policy.art = ArtMode::One;
+        options.max_sizes = vec!["art=32B".parse().unwrap()];
+        options.rules = vec!["purge if name ~ \"^cover.jpg$\"".parse().unwrap()];
+        let plan = build_purge_file_list(get_library_paths(library.to_str().unwrap()), &options);
+
+        // The best art that survives the size limits and rules is kept,
+        // rather than leaving the album with no art at all.
+        assert_eq!(plan.purge_files.len(), 3);
+        assert!(list_contains_file(&plan.purge_files, "Album/cover.tif"));
+        assert!(list_contains_file(&plan.purge_files, "Album/front.jpg"));
+        assert!(list_contains_file(&plan.purge_files, "Ruled/cover.jpg"));
+        assert_eq!(
+            plan.purge_reasons[&library.join("Album/front.jpg")], "--art-mode one keeps folder.jpg"
+        );
+    }
+
+    #[test]
+    fn build_purge_file_list_art_if_embedded() {
+        // An ID3v2.3 tag, with a front cover.
+        let tagged = "ID3\x03\0\0\0\0\0\x1cAPIC\0\0\0\x12\0\0\0image/jpeg\0\x03\0jpeg";
+        let library = setup_test_library("art_embedded", &[
+            ("Embedded/01.mp3", tagged),
+            ("Embedded/02.mp3", tagged),
+            ("Embedded/cover.jpg", ""),
+            ("Mixed/01.mp3", tagged),
+            ("Mixed/02.mp3", ""),
+            ("Mixed/cover.jpg", ""),
+            ("Unreadable/01.wav", ""),
+            ("Unreadable/cover.jpg", ""),
+        ]);
+        let mut options = test_options(false, false, false);
+        options.policy.art = ArtMode::IfEmbedded;
+        let plan = build_purge_file_list(get_library_paths(library.to_str().unwrap()), &options);
+
+        // Only the folder whose tracks all have art loses its folder art.
+        assert_eq!(plan.purge_files, vec![library.join("Embedded/cover.jpg")]);
+        assert_eq!(plan.purge_reasons[&plan.purge_files[0]], "all of the album's music embeds art");
+    }
+
+    #[test]
+    fn plan_art_extraction_for_folders_without_art() {
+        let _ = fs::remove_dir_all("tests/libraries/art_extract");
+        let library = setup_test_library("art_extract", &[
+            ("HasArt/01.mp3", ""),
+            ("HasArt/cover.jpg", ""),
+            ("NoArt/01.mp3", ""),
+            ("NoArt/02.mp3", ""),
+            ("NoArt/03.mp3", ""),
+            ("Untagged/01.mp3", ""),
+            ("Unknown/01.mp3", ""),
+        ]);
+        for (path, image) in [
+            ("HasArt/01.mp3", &b"\xff\xd8\xff"[..]),
+            ("NoArt/02.mp3", b"\x89PNG\r\n\x1a\n"),
+            ("NoArt/03.mp3", b"\xff\xd8\xff"),
+            ("Unknown/01.mp3", b"jpeg"),
+        ] {
+            fs::write(library.join(path), id3_tag(image)).unwrap();
+        }
+        let library_paths = get_library_paths(library.to_str().unwrap());
+        let (extractions, warnings) =
+            plan_art_extraction(&library_paths, &ArtMatcher::default(), "folder.jpg");
+
+        // The first track with art is used, and its image type names the file.
+        assert_eq!(extractions, vec![ArtExtraction {
+            source: library.join("NoArt/02.mp3"), target: library.join("NoArt/folder.png")
+        }]);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("Unknown/01.mp3"));
+
+        // Simulation writes nothing; the art is written once, and never overwritten.
+        extract_art(&extractions[0], false).unwrap();
+        assert!(!extractions[0].target.exists());
+        extract_art(&extractions[0], true).unwrap();
+        assert_eq!(fs::read(&extractions[0].target).unwrap(), b"\x89PNG\r\n\x1a\n");
+        let error = extract_art(&extractions[0], true).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+    }
+
+    #[test]
+    fn plan_art_extraction_uses_art_options() {
+        let library = setup_test_library("art_extract_options", &[("Album/scan.jpg", "")]);
+        fs::write(library.join("Album/01.mp3"), id3_tag(b"\xff\xd8\xff")).unwrap();
+        let library_paths = get_library_paths(library.to_str().unwrap());
+
+        // Art that is only recognized by --art-pattern still counts as the
+        // folder's art, so no more is extracted.
+        let (extractions, _) =
+            plan_art_extraction(&library_paths, &ArtMatcher::default(), "folder");
+        assert_eq!(extractions.len(), 1);
+        let art = ArtMatcher::new(vec![parse_art_pattern("^scan$").unwrap()], vec![], vec![]);
+        let (extractions, _) = plan_art_extraction(&library_paths, &art, "folder");
+        assert!(extractions.is_empty());
+
+        // Only an art extension in the name is replaced.
+        let art = ArtMatcher::default();
+        for (name, target) in [("cover.PNG", "cover.jpg"), ("my.cover", "my.cover.jpg")] {
+            let (extractions, _) = plan_art_extraction(&library_paths, &art, name);
+            assert_eq!(extractions[0].target, library.join("Album").join(target));
+        }
+    }
+
+    #[test]
+    fn build_purge_file_list_orphaned_art() {
+        let library = setup_test_library("art_orphans", &[
+            ("Album/01.mp3", ""),
+            ("Album/cover.jpg", ""),
+            ("Album/Scans/folder.jpg", ""),
+            ("Multi/folder.jpg", ""),
+            ("Multi/CD1/01.flac", ""),
+            ("Multi/Disc 2/01.flac", ""),
+            ("Extras/folder.jpg", ""),
+            ("Extras/Photos/notes.txt", ""),
+        ]);
+        let mut options = test_options(false, false, false);
+        options.library_root = library.clone();
+        let plan = build_purge_file_list(get_library_paths(library.to_str().unwrap()), &options);
+
+        // Art is kept with music, or above its discs, but not on its own.
+        assert_eq!(plan.purge_files.len(), 3);
+        assert!(list_contains_file(&plan.purge_files, "Album/Scans/folder.jpg"));
+        assert!(list_contains_file(&plan.purge_files, "Extras/folder.jpg"));
+        assert_eq!(
+            plan.purge_reasons[&library.join("Extras/folder.jpg")], "no music in the art's album"
+        );
+        // Only the top-most folders without music are reported.
+        assert_eq!(plan.no_music_folders, vec![library.join("Album/Scans"), library.join("Extras")]);
+    }
+
+    #[test]
+    fn build_purge_file_list_multi_disc_albums() {
+        let _ = fs::remove_dir_all("tests/libraries/multi_disc");
+        let library = setup_test_library("multi_disc", &[
+            ("One/folder.jpg", ""),
+            ("One/CD1/01.flac", ""),
+            ("One/CD1/cover.jpg", ""),
+            ("One/CD2/01.flac", ""),
+            ("One/CD3/front.jpg", ""),
+            ("Embedded/cover.jpg", ""),
+            ("Embedded/Disc 1/01.mp3", ""),
+            ("Embedded/Disc 2/01.mp3", ""),
+            ("Extract/Disc 1/01.mp3", ""),
+            ("Extract/Disc 2/01.mp3", ""),
+        ]);
+        for path in ["Embedded/Disc 1/01.mp3", "Extract/Disc 1/01.mp3", "Extract/Disc 2/01.mp3"] {
+            fs::write(library.join(path), id3_tag(b"\xff\xd8\xff")).unwrap();
+        }
+        let library_paths = get_library_paths(library.to_str().unwrap());
+        let mut options = test_options(false, false, false);
+        options.library_root = library.clone();
+        options.folder_configs.insert(library.join("One"), toml::from_str("art = \"one\"").unwrap());
+        options.policy.art = ArtMode::IfEmbedded;
+        let plan = build_purge_file_list(library_paths.clone(), &options);
+
+        // An album keeps one art file across its discs, preferring that in the
+        // album folder (and an empty disc is still part of it) ...
+        assert!(!list_contains_file(&plan.purge_files, "One/folder.jpg"));
+        assert!(list_contains_file(&plan.purge_files, "One/CD1/cover.jpg"));
+        assert!(list_contains_file(&plan.purge_files, "One/CD3/front.jpg"));
+        assert!(plan.no_music_folders.is_empty());
+        // ... its art is only purged if the music on every disc embeds art ...
+        assert!(!list_contains_file(&plan.purge_files, "Embedded/cover.jpg"));
+        assert_eq!(plan.purge_files.len(), 2);
+
+        // ... and art is extracted to the album folder, from its first disc.
+        let (extractions, _) = plan_art_extraction(&library_paths, &ArtMatcher::default(), "folder");
+        assert_eq!(extractions, vec![ArtExtraction {
+            source: library.join("Extract/Disc 1/01.mp3"), target: library.join("Extract/folder.jpg")
+        }]);
+    }
+
+    #[test]
+    fn is_disc_folder_names() {
+        for name in ["CD1", "cd 2", "Disc 1", "DISC_03", "Disk-2", "CD1 - Live"] {
+            assert!(is_disc_folder(Path::new(name)), "{} should be a disc", name);
+        }
+        for name in ["Album", "CDs", "Discography", "Disc"] {
+            assert!(!is_disc_folder(Path::new(name)), "{} should not be a disc", name);
+        }
+    }
+
+    #[test]
+    fn build_purge_file_list_art_renames() {
+        let library = setup_test_library("art_names", &[
+            ("Named/01.mp3", ""),
+            ("Upper/01.mp3", ""),
+            ("Png/01.mp3", ""),
+            ("Taken/01.mp3", ""),
+            ("Named/cover.jpg", ""),
+            ("Named/folder.jpg", ""),
+            ("Upper/Folder.JPEG", ""),
+            ("Upper/front.png", ""),
+            ("Png/front.png", ""),
+            ("Taken/folder.jpg", ""),
+            ("Taken/COVER.jpg", ""),
+        ]);
+        let library_paths = get_library_paths(library.to_str().unwrap());
+        let mut options = test_options(false, false, false);
+        options.art_name = Some(String::from("cover.jpg"));
+        options.art_priority = vec![String::from("folder")];
+        let plan = build_purge_file_list(library_paths.clone(), &options);
+
+        // Kept art is renamed, unless it is in another format, or the name is
+        // already taken by art that is kept.
+        assert_eq!(plan.art_renames, vec![
+            ArtRename { from: library.join("Upper/Folder.JPEG"), to: library.join("Upper/cover.jpg") },
+        ]);
+        assert_eq!(plan.warnings.len(), 3);
+        assert!(plan.warnings.iter().any(|w| w.contains("Png/front.png") && w.contains("not a jpg")));
+        assert!(plan.warnings.iter().any(|w| w.contains("Named/folder.jpg") && w.contains("exists")));
+        // (A name differing only in case is the same file on some systems.)
+        assert!(plan.warnings.iter().any(|w| w.contains("Taken/folder.jpg") && w.contains("exists")));
+
+        // With "--art-mode one", the art in the way is purged, and the rename can go
+        // ahead; a name without an extension keeps the art's own.
+        options.policy.art = ArtMode::One;
+        options.art_name = Some(String::from("cover"));
+        let plan = build_purge_file_list(library_paths, &options);
+        assert!(list_contains_file(&plan.purge_files, "Named/cover.jpg"));
+        assert!(plan.art_renames.contains(
+            &ArtRename { from: library.join("Png/front.png"), to: library.join("Png/cover.png") }
+        ));
+        assert_eq!(plan.art_renames.len(), 4);
+        assert!(plan.warnings.is_empty());
+    }
+
+    #[test]
+    fn rename_or_copy_file_renames_and_copies() {
+        let _ = fs::remove_dir_all("tests/libraries/art_rename");
+        let library = setup_test_library("art_rename", &[
+            ("Album/Folder.jpg", "art"),
+            ("Album/front.jpg", "more art"),
+        ]);
+        let rename = |from: &str, to: &str| ArtRename { from: library.join(from), to: library.join(to) };
+        let backup = library.join("backup");
+
+        // Simulation changes nothing, and a rename is backed up first ...
+        let folder = rename("Album/Folder.jpg", "Album/cover.jpg");
+        rename_or_copy_file(&folder, &library, &backup, true, false, false).unwrap();
+        assert!(folder.from.exists() && !folder.to.exists());
+        rename_or_copy_file(&folder, &library, &backup, true, false, true).unwrap();
+        assert!(!folder.from.exists() && folder.to.exists());
+        assert!(backup.join("Album/Folder.jpg").exists());
+
+        // ... a copy keeps the original, and nothing is ever overwritten.
+        let front = rename("Album/front.jpg", "Album/folder.jpg");
+        rename_or_copy_file(&front, &library, &backup, false, true, true).unwrap();
+        assert!(front.from.exists() && front.to.exists());
+        let clash = rename("Album/front.jpg", "Album/cover.jpg");
+        let error = rename_or_copy_file(&clash, &library, &backup, false, false, true).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(library.join("Album/cover.jpg")).unwrap(), "art");
+    }
+
+    #[test]
+    fn folder_config_art_modes() {
+        let art = |text: &str| toml::from_str::<FolderConfig>(text).unwrap()
+            .apply(CategoryPolicy::default()).art;
+        assert_eq!(art("art = true"), ArtMode::Purge);
+        assert_eq!(art("art = false"), ArtMode::Keep);
+        assert_eq!(art("art = \"one\""), ArtMode::One);
+        assert_eq!(art("art = \"if-embedded\""), ArtMode::IfEmbedded);
+        assert!(toml::from_str::<FolderConfig>("art = \"some\"").is_err());
+    }
+
+    #[test]
+    fn time_limit_from_str() {
+        assert!("30d".parse::<TimeLimit>().unwrap().time < SystemTime::now());
+        assert_eq!(
+            "1970-01-02".parse::<TimeLimit>().unwrap().time,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(86400)
+        );
+        assert!("yesterday".parse::<TimeLimit>().is_err());
+    }
+
+    #[test]
+    fn format_age_units() {
+        assert_eq!(format_age(Duration::from_secs(40)), "40s");
+        assert_eq!(format_age(Duration::from_secs(3 * 3600 + 5)), "3h");
+        assert_eq!(format_age(Duration::from_secs(10 * 86400)), "10d");
+    }
+
+    #[test]
+    fn size_limit_from_str() {
+        let limit: SizeLimit = "art=2MB".parse().unwrap();
+        assert_eq!(limit, SizeLimit { category: FileCategory::Art, size: 2 * 1024 * 1024 });
+        assert!("2MB".parse::<SizeLimit>().is_err());
+        assert!("movie=2MB".parse::<SizeLimit>().is_err());
+        assert!("art=huge".parse::<SizeLimit>().is_err());
+
+        // Limits are shown as they are given, so they can be parsed again.
+        for text in ["audio=1536B", "rip-metadata=2MB", "document=20MB", "art=1GB"] {
+            let limit: SizeLimit = text.parse().unwrap();
+            assert_eq!(limit.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn load_folder_configs_rejects_unknown_keys() {
+        let library = setup_test_library("policy_invalid", &[
+            (".mlcp.toml", "other_audoi = true\n"),
+        ]);
+        assert!(load_folder_configs(&get_library_paths(library.to_str().unwrap())).is_err());
+    }
+
+    #[test]
+    fn ignore_reason_names_file_and_pattern() {
+        let library = setup_test_library("ignore_reason", &[
+            (".mlcpignore", "*.html\n"),
+            ("notes.html", ""),
+        ]);
+        let ignore_files = load_ignore_files(&get_library_paths(library.to_str().unwrap()));
+        let reason = ignore_reason(&ignore_files, &library.join("notes.html")).unwrap();
+        assert!(reason.ends_with(".mlcpignore: *.html"));
+    }
+
     #[test]
     fn backup_file_success() {
         // Create file to test backup against.
@@ -740,6 +3778,38 @@ mod tests {
         fs::remove_file("tests/no_purge.tst").unwrap();       
     }
 
+    #[test]
+    fn purge_or_backup_file_missing_file_error() {
+        // Purging a file that doesn't exist fails ...
+        let result = purge_or_backup_file(
+            &PathBuf::from("tests/does_not_exist.tst"),
+            &PathBuf::from("tests/"),
+            &PathBuf::from(""),
+            false,
+            true
+        );
+        
+        // ... and the error keeps both the operation and the OS error kind.
+        match result {
+            Err(e @ PurgeError::Purge { .. }) => assert_eq!(e.kind(), io::ErrorKind::NotFound),
+            other => panic!("Expected a purge error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn summarize_errors_groups_by_kind() {
+        let error = |kind: io::ErrorKind| PurgeError::Purge {
+            path: PathBuf::from("file"), source: io::Error::from(kind)
+        };
+        let errors = vec![
+            error(io::ErrorKind::NotFound),
+            error(io::ErrorKind::PermissionDenied),
+            error(io::ErrorKind::PermissionDenied),
+        ];
+        // Most frequent kind first.
+        assert_eq!(summarize_errors(&errors), "2 permission denied, 1 entity not found");
+    }
+
     // Test Helper/Setup/Teardown Functions
 
     // Create common test files.
@@ -762,12 +3832,58 @@ mod tests {
         });          
     }
 
+    // Purge options for the given category flags, with no scope patterns.
+    fn test_options(delete_art: bool, keep_other_audio: bool, keep_documents: bool) -> PurgeOptions {
+        let art = if delete_art { ArtMode::Purge } else { ArtMode::Keep };
+        PurgeOptions {
+            policy: CategoryPolicy {
+                art, keep_other_audio, keep_documents, keep_rip_metadata: false, keep_video: false,
+                keep_playlists: false, keep_junk: false
+            },
+            art_priority: ART_NAME_PRIORITY.map(String::from).to_vec(),
+            ..Default::default()
+        }
+    }
+
+    // Create a test library, under "tests/libraries", from (path, content) pairs.
+    fn setup_test_library(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let library = std::env::current_dir().unwrap().join("tests/libraries").join(name);
+        for (path, content) in files {
+            let file = library.join(path);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, content).unwrap();
+        }
+        library
+    }
+
+    // The header of a PNG image of the given size; enough to read its resolution.
+    fn png_header(width: u32, height: u32) -> Vec<u8> {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend(width.to_be_bytes());
+        png.extend(height.to_be_bytes());
+        png.extend([8, 6, 0, 0, 0, 0, 0, 0, 0]);
+        png
+    }
+
+    // An ID3v2.3 tag, with an image as its front cover.
+    fn id3_tag(image: &[u8]) -> Vec<u8> {
+        let mut frame = b"\0image/jpeg\0\x03\0".to_vec();
+        frame.extend(image);
+        let mut tag = b"ID3\x03\0\0\0\0\0".to_vec();
+        tag.push(frame.len() as u8 + 10);
+        tag.extend(b"APIC\0\0\0");
+        tag.push(frame.len() as u8);
+        tag.extend([0, 0]);
+        tag.extend(frame);
+        tag
+    }
+
     // Get the library paths for various tests.
     fn get_test_library_paths() -> Vec<PathBuf> {
         let cwd = std::env::current_dir().unwrap();
         let test_path = cwd.join("tests/library/");
         let lib_path = test_path.to_str().unwrap();
-        get_library_paths(&lib_path)
+        get_library_paths(lib_path)
     }
 
     // Determine if a list of paths contains a specific file.
@@ -0,0 +1,166 @@
+// mlcp - Music Library "Crud" Purge - Copyright (C) 2022, Ian Dunmore
+//
+// Free and open-source software, published under the MIT license; see
+// LICENSE file for more details.
+
+// Reads the files referenced by playlists, from:
+//
+//   .m3u/.m3u8: one path (or URL) per line; "#" starts a comment or directive
+//   .pls: "FileN=path" entries
+//   .xspf: <location> elements, holding (usually "file://") URIs
+//
+// Relative paths are resolved against the playlist's folder, and may use "\"
+// as the separator; every path is made absolute, so it can be compared with
+// the library's paths however each was written.  Streams and other URLs are
+// ignored, as are entries that can't be parsed.
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+// The files referenced by a playlist, in the order they appear.
+pub fn read_entries(playlist: &Path) -> io::Result<Vec<PathBuf>> {
+    let text = String::from_utf8_lossy(&fs::read(playlist)?).into_owned();
+    let extension = playlist.extension().unwrap_or_default().to_string_lossy();
+    let locations = match extension.to_lowercase().as_str() {
+        "m3u" | "m3u8" => parse_m3u(&text),
+        "pls" => parse_pls(&text),
+        "xspf" => parse_xspf(&text),
+        _ => Vec::new(),
+    };
+
+    let folder = playlist.parent().unwrap_or(Path::new(""));
+    Ok(locations.iter()
+        .filter_map(|location| location_to_path(location))
+        .map(|path| resolve(&folder.join(path)))
+        .collect())
+}
+
+// Parses the path of each entry of an M3U playlist.
+fn parse_m3u(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|line| line.trim().trim_start_matches('\u{feff}'))
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+// Parses the path of each "FileN=" entry of a PLS playlist.
+fn parse_pls(text: &str) -> Vec<String> {
+    text.lines()
+        .filter_map(|line| {
+            let (key, value) = line.trim().split_once('=')?;
+            let key = key.trim().to_lowercase();
+            let number = key.strip_prefix("file")?;
+            if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) { return None; }
+            Some(value.trim().to_string())
+        })
+        .collect()
+}
+
+// Parses the URI in each <location> element of an XSPF playlist.
+fn parse_xspf(text: &str) -> Vec<String> {
+    let mut locations = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("<location>") {
+        rest = &rest[start + "<location>".len()..];
+        let Some(end) = rest.find("</location>") else { break };
+        locations.push(decode_xml(rest[..end].trim()));
+        rest = &rest[end..];
+    }
+    locations
+}
+
+// The local path of a playlist location; None for a stream or other URL.
+fn location_to_path(location: &str) -> Option<PathBuf> {
+    let path = match location.split_once("://") {
+        Some((scheme, path)) if scheme.eq_ignore_ascii_case("file") => decode_uri(path),
+        // Anything else with a scheme is a URL (but not a Windows drive).
+        Some(_) => return None,
+        None => location.to_string(),
+    };
+    Some(PathBuf::from(path.replace('\\', "/")))
+}
+
+// The absolute form of a path, relative to the current folder, with "." and
+// ".." resolved; without touching the file system, so it needn't exist.
+pub fn resolve(path: &Path) -> PathBuf {
+    normalize(&std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()))
+}
+
+// Resolves "." and ".." in a path, without touching the file system.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => { normalized.pop(); }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+// Decodes the "%XX" escapes in a URI.
+fn decode_uri(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes.get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => { decoded.push(byte); i += 3; }
+            (byte, _) => { decoded.push(byte); i += 1; }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// Decodes the predefined XML entities.
+fn decode_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_playlist_entries() {
+        let m3u = parse_m3u(
+            "\u{feff}#EXTM3U\r\n#EXTINF:5,Intro\r\n00 - Intro.opus\r\n\r\nCD2\\01.flac\r\n"
+        );
+        assert_eq!(m3u, vec!["00 - Intro.opus", "CD2\\01.flac"]);
+
+        let pls = parse_pls("[playlist]\nFile1=01.mp3\nTitle1=One\nfile2 = 02.mp3\nVersion=2\n");
+        assert_eq!(pls, vec!["01.mp3", "02.mp3"]);
+
+        let xspf = parse_xspf(
+            "<playlist><trackList><track><location>file:///music/Rock%20%26%20Roll/01.mp3\
+             </location></track><track><location>a&amp;b.mp3</location></track></trackList>\
+             </playlist>"
+        );
+        assert_eq!(xspf, vec!["file:///music/Rock%20%26%20Roll/01.mp3", "a&b.mp3"]);
+    }
+
+    #[test]
+    fn locations_resolve_to_paths() {
+        assert_eq!(
+            location_to_path("file:///music/Rock%20%26%20Roll/01.mp3"),
+            Some(PathBuf::from("/music/Rock & Roll/01.mp3"))
+        );
+        assert_eq!(location_to_path("..\\Other\\01.mp3"), Some(PathBuf::from("../Other/01.mp3")));
+        assert_eq!(location_to_path("http://radio.example.com/stream"), None);
+        assert_eq!(normalize(Path::new("/music/A/../B/./01.mp3")), Path::new("/music/B/01.mp3"));
+        assert_eq!(
+            resolve(Path::new("./Album/../01.mp3")),
+            std::env::current_dir().unwrap().join("01.mp3")
+        );
+    }
+}
@@ -0,0 +1,546 @@
+// mlcp - Music Library "Crud" Purge - Copyright (C) 2022, Ian Dunmore
+//
+// Free and open-source software, published under the MIT license; see
+// LICENSE file for more details.
+
+// Rule expressions, deciding per file whether to purge or keep it, e.g.:
+//
+//   purge if ext in [jpg, png] and size > 2MB and not name ~ "^(cover|folder)"
+//
+// Fields: ext, name, path (relative to the library root), size and age (of
+// the file's modification time).  Operators: "==", "!=", "in [..]" and "~"
+// (regex) for ext, name and path; "==", "!=", ">", ">=", "<" and "<=" for
+// size and age.  Conditions combine with "and", "or", "not" and parentheses.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use regex::Regex;
+use serde::Deserialize;
+
+// Size Units (Binary), for values such as "2MB".
+const SIZE_UNITS: [(&str, u64); 5] = [
+    ("b", 1), ("kb", 1 << 10), ("mb", 1 << 20), ("gb", 1 << 30), ("tb", 1 << 40)
+];
+
+// Duration Units, for values such as "30d".
+const DURATION_UNITS: [(&str, u64); 5] = [
+    ("s", 1), ("m", 60), ("h", 60 * 60), ("d", 24 * 60 * 60), ("w", 7 * 24 * 60 * 60)
+];
+
+// What to do with a file when a rule matches it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleAction {
+    Purge,
+    Keep,
+}
+
+// The facts about a file that rules are evaluated against.
+pub struct FileFacts {
+    // Lower-cased extension, without the ".".
+    pub ext: String,
+    pub name: String,
+    // Path relative to the library root.
+    pub path: String,
+    pub size: u64,
+    // Time since the file was last modified.
+    pub age: Duration,
+}
+
+// A parsed rule: an action, and the condition under which it applies.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Rule {
+    pub action: RuleAction,
+    condition: Expr,
+    text: String,
+}
+
+impl Rule {
+    // Determines whether this rule applies to a file.
+    pub fn matches(&self, facts: &FileFacts) -> bool {
+        self.condition.evaluate(facts)
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { tokens: tokenize(text)?, position: 0 };
+        let action = match parser.next() {
+            Some(Token::Word(w)) if w == "purge" => RuleAction::Purge,
+            Some(Token::Word(w)) if w == "keep" => RuleAction::Keep,
+            _ => return Err(String::from("rule must start with \"purge\" or \"keep\"")),
+        };
+        parser.expect_word("if")?;
+        let condition = parser.parse_or()?;
+        if let Some(token) = parser.next() {
+            return Err(format!("unexpected {} after condition", token));
+        }
+        Ok(Rule { action, condition, text: String::from(text.trim()) })
+    }
+}
+
+impl TryFrom<String> for Rule {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+// Parses a size, in bytes, with an optional binary unit (e.g. "2MB", "500kb").
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    parse_quantity(text, &SIZE_UNITS, "b").ok_or(format!("invalid size \"{}\"", text))
+}
+
+// Parses a duration with a unit of s, m, h, d or w (e.g. "30d", "12h").
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    parse_quantity(text, &DURATION_UNITS, "")
+        .map(Duration::from_secs)
+        .ok_or(format!("invalid duration \"{}\" (expected e.g. 30d, 12h, 2w)", text))
+}
+
+// Parses a UTC date, optionally with a time, as "YYYY-MM-DD[THH:MM[:SS]]".
+pub fn parse_date(text: &str) -> Result<SystemTime, String> {
+    let invalid = || format!("invalid date \"{}\" (expected e.g. 2024-01-31)", text);
+    let (date, time) = text.trim().split_once(['T', ' ']).unwrap_or((text.trim(), "00:00"));
+
+    let date_parts: Vec<&str> = date.split('-').collect();
+    let time_parts: Vec<&str> = time.split(':').collect();
+    if date_parts.len() != 3 || !(2..=3).contains(&time_parts.len()) { return Err(invalid()); }
+    let number = |part: &str| part.parse::<i64>().map_err(|_| invalid());
+    let (year, month, day) =
+        (number(date_parts[0])?, number(date_parts[1])?, number(date_parts[2])?);
+    let (hour, minute) = (number(time_parts[0])?, number(time_parts[1])?);
+    let second = if time_parts.len() == 3 { number(time_parts[2])? } else { 0 };
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+        return Err(invalid());
+    }
+    if hour > 23 || minute > 59 || second > 59 { return Err(invalid()); }
+
+    let time_of_day = hour * 3600 + minute * 60 + second;
+    let seconds = days_from_civil(year, month, day) * 86400 + time_of_day;
+    u64::try_from(seconds)
+        .map(|s| UNIX_EPOCH + Duration::from_secs(s))
+        .map_err(|_| invalid())
+}
+
+// The number of days in a month, allowing for leap years.
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 for a (proleptic Gregorian) date; see
+// http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+// Parses a whole number followed by one of the units, which scale it.
+fn parse_quantity(text: &str, units: &[(&str, u64)], default_unit: &str) -> Option<u64> {
+    let text = text.trim().to_lowercase();
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let unit = if unit.is_empty() { default_unit } else { unit.trim() };
+
+    let scale = units.iter().find(|(name, _)| *name == unit)?.1;
+    number.parse::<u64>().ok()?.checked_mul(scale)
+}
+
+// Rule Conditions
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextField {
+    Ext,
+    Name,
+    Path,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NumberField {
+    Size,
+    Age,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    // Text comparisons; "!=" is parsed as Not(TextIn).
+    TextIn(TextField, Vec<String>),
+    TextMatches(TextField, Regex),
+    Number(NumberField, Comparison, u64),
+}
+
+impl Expr {
+    fn evaluate(&self, facts: &FileFacts) -> bool {
+        match self {
+            Expr::And(a, b) => a.evaluate(facts) && b.evaluate(facts),
+            Expr::Or(a, b) => a.evaluate(facts) || b.evaluate(facts),
+            Expr::Not(a) => !a.evaluate(facts),
+            Expr::TextIn(field, values) => {
+                let text = text_fact(*field, facts);
+                values.iter().any(|v| v == text)
+            }
+            Expr::TextMatches(field, regex) => regex.is_match(text_fact(*field, facts)),
+            Expr::Number(field, comparison, value) => {
+                let number = match field {
+                    NumberField::Size => facts.size,
+                    NumberField::Age => facts.age.as_secs(),
+                };
+                match comparison {
+                    Comparison::Equal => number == *value,
+                    Comparison::NotEqual => number != *value,
+                    Comparison::Greater => number > *value,
+                    Comparison::GreaterOrEqual => number >= *value,
+                    Comparison::Less => number < *value,
+                    Comparison::LessOrEqual => number <= *value,
+                }
+            }
+        }
+    }
+}
+
+fn text_fact(field: TextField, facts: &FileFacts) -> &str {
+    match field {
+        TextField::Ext => &facts.ext,
+        TextField::Name => &facts.name,
+        TextField::Path => &facts.path,
+    }
+}
+
+// Tokenizer
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    // Keywords, field names and unquoted values (e.g. "jpg", "2MB").
+    Word(String),
+    // Quoted strings, with "\" escapes.
+    Quoted(String),
+    Symbol(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(w) => write!(f, "\"{}\"", w),
+            Token::Quoted(q) => write!(f, "\"{}\"", q),
+            Token::Symbol(s) => write!(f, "\"{}\"", s),
+        }
+    }
+}
+
+// Symbols, longest first so "==" isn't read as two tokens.
+const SYMBOLS: [&str; 11] = ["==", "!=", ">=", "<=", ">", "<", "~", "(", ")", "[", "]"];
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() || c == ',' {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut quoted = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, '\\')) => match chars.next() {
+                        // Keep escapes other than \" intact, for regexes.
+                        Some((_, '"')) => quoted.push('"'),
+                        Some((_, e)) => { quoted.push('\\'); quoted.push(e); }
+                        None => return Err(String::from("unterminated string")),
+                    },
+                    Some((_, q)) => quoted.push(q),
+                    None => return Err(String::from("unterminated string")),
+                }
+            }
+            tokens.push(Token::Quoted(quoted));
+        } else if let Some(symbol) = SYMBOLS.iter().find(|s| text[start..].starts_with(**s)) {
+            for _ in 0..symbol.len() { chars.next(); }
+            tokens.push(Token::Symbol(symbol));
+        } else {
+            let mut word = String::new();
+            while let Some(&(_, w)) = chars.peek() {
+                if w.is_whitespace() || w == ',' || w == '"' || "=!<>~()[]".contains(w) { break; }
+                word.push(w);
+                chars.next();
+            }
+            if word.is_empty() { return Err(format!("unexpected character '{}'", c)); }
+            tokens.push(Token::Word(word));
+        }
+    }
+    Ok(tokens)
+}
+
+// Parser (recursive descent; "not" binds tightest, then "and", then "or").
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek_word(&self, word: &str) -> bool {
+        matches!(self.tokens.get(self.position), Some(Token::Word(w)) if w == word)
+    }
+
+    fn expect_word(&mut self, word: &str) -> Result<(), String> {
+        match self.next() {
+            Some(Token::Word(w)) if w == word => Ok(()),
+            Some(token) => Err(format!("expected \"{}\", found {}", word, token)),
+            None => Err(format!("expected \"{}\"", word)),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_and()?;
+        while self.peek_word("or") {
+            self.position += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_not()?;
+        while self.peek_word("and") {
+            self.position += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_not()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, String> {
+        if self.peek_word("not") {
+            self.position += 1;
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Symbol("(")) => {
+                let expr = self.parse_or()?;
+                match self.next() {
+                    Some(Token::Symbol(")")) => Ok(expr),
+                    _ => Err(String::from("expected \")\"")),
+                }
+            }
+            Some(Token::Word(field)) => self.parse_comparison(&field),
+            Some(token) => Err(format!("expected a condition, found {}", token)),
+            None => Err(String::from("expected a condition")),
+        }
+    }
+
+    fn parse_comparison(&mut self, field: &str) -> Result<Expr, String> {
+        match field {
+            "ext" => self.parse_text_comparison(TextField::Ext),
+            "name" => self.parse_text_comparison(TextField::Name),
+            "path" => self.parse_text_comparison(TextField::Path),
+            "size" => self.parse_number_comparison(NumberField::Size),
+            "age" => self.parse_number_comparison(NumberField::Age),
+            _ => Err(format!(
+                "unknown field \"{}\" (expected ext, name, path, size or age)", field
+            )),
+        }
+    }
+
+    fn parse_text_comparison(&mut self, field: TextField) -> Result<Expr, String> {
+        // Extensions are compared case-insensitively, without a leading ".".
+        let normalize = |v: String| match field {
+            TextField::Ext => v.trim_start_matches('.').to_lowercase(),
+            _ => v,
+        };
+        match self.next() {
+            Some(Token::Symbol("==")) => Ok(Expr::TextIn(field, vec![normalize(self.value()?)])),
+            Some(Token::Symbol("!=")) => Ok(Expr::Not(Box::new(
+                Expr::TextIn(field, vec![normalize(self.value()?)])
+            ))),
+            Some(Token::Symbol("~")) => {
+                let pattern = self.value()?;
+                Regex::new(&pattern)
+                    .map(|regex| Expr::TextMatches(field, regex))
+                    .map_err(|e| format!("invalid regex \"{}\": {}", pattern, e))
+            }
+            Some(Token::Word(w)) if w == "in" => {
+                Ok(Expr::TextIn(field, self.list()?.into_iter().map(normalize).collect()))
+            }
+            _ => Err(String::from("expected \"==\", \"!=\", \"~\" or \"in\" after text field")),
+        }
+    }
+
+    fn parse_number_comparison(&mut self, field: NumberField) -> Result<Expr, String> {
+        let comparison = match self.next() {
+            Some(Token::Symbol("==")) => Comparison::Equal,
+            Some(Token::Symbol("!=")) => Comparison::NotEqual,
+            Some(Token::Symbol(">")) => Comparison::Greater,
+            Some(Token::Symbol(">=")) => Comparison::GreaterOrEqual,
+            Some(Token::Symbol("<")) => Comparison::Less,
+            Some(Token::Symbol("<=")) => Comparison::LessOrEqual,
+            _ => return Err(String::from("expected a comparison after size or age")),
+        };
+        let value = self.value()?;
+        let number = match field {
+            NumberField::Size => parse_size(&value)?,
+            NumberField::Age => parse_duration(&value)?.as_secs(),
+        };
+        Ok(Expr::Number(field, comparison, number))
+    }
+
+    fn value(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Word(w)) | Some(Token::Quoted(w)) => Ok(w),
+            _ => Err(String::from("expected a value")),
+        }
+    }
+
+    fn list(&mut self) -> Result<Vec<String>, String> {
+        if self.next() != Some(Token::Symbol("[")) { return Err(String::from("expected \"[\"")); }
+        let mut values = Vec::new();
+        loop {
+            match self.next() {
+                Some(Token::Symbol("]")) => return Ok(values),
+                Some(Token::Word(w)) | Some(Token::Quoted(w)) => values.push(w),
+                _ => return Err(String::from("expected a value or \"]\"")),
+            }
+        }
+    }
+}
+
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 3MB "back.jpg" file, modified 10 days ago.
+    fn test_facts() -> FileFacts {
+        FileFacts {
+            ext: String::from("jpg"),
+            name: String::from("back.jpg"),
+            path: String::from("Artist/Album/back.jpg"),
+            size: 3 * 1024 * 1024,
+            age: Duration::from_secs(10 * 24 * 60 * 60),
+        }
+    }
+
+    fn matches(rule: &str) -> bool {
+        rule.parse::<Rule>().unwrap().matches(&test_facts())
+    }
+
+    #[test]
+    fn parse_action() {
+        assert_eq!("purge if ext == jpg".parse::<Rule>().unwrap().action, RuleAction::Purge);
+        assert_eq!("keep if ext == jpg".parse::<Rule>().unwrap().action, RuleAction::Keep);
+        assert!("delete if ext == jpg".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!("purge ext == jpg".parse::<Rule>().is_err());
+        assert!("purge if colour == red".parse::<Rule>().is_err());
+        assert!("purge if size ~ big".parse::<Rule>().is_err());
+        assert!("purge if size > lots".parse::<Rule>().is_err());
+        assert!("purge if (ext == jpg".parse::<Rule>().is_err());
+        assert!("purge if name ~ \"[\"".parse::<Rule>().is_err());
+        assert!("purge if ext == jpg jpg".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn text_comparisons() {
+        assert!(matches("purge if ext == JPG"));
+        assert!(matches("purge if ext != png"));
+        assert!(matches("purge if ext in [png, jpg]"));
+        assert!(!matches("purge if ext in [\"png\", \"gif\"]"));
+        assert!(matches("purge if name ~ \"^back\""));
+        assert!(matches("purge if path ~ \"^Artist/\""));
+    }
+
+    #[test]
+    fn number_comparisons() {
+        assert!(matches("purge if size > 2MB"));
+        assert!(!matches("purge if size <= 2MB"));
+        assert!(matches("purge if age >= 7d"));
+        assert!(!matches("purge if age > 2w"));
+    }
+
+    #[test]
+    fn combined_conditions() {
+        // The example from the documentation: large, non-cover art.
+        assert!(matches(
+            "purge if ext in [jpg,png] and size > 2MB and not name ~ \"^(cover|folder)\""
+        ));
+        // "and" binds tighter than "or"; parentheses override it.
+        assert!(matches("purge if ext == png and size > 1GB or age > 1d"));
+        assert!(!matches("purge if ext == png and (size > 1GB or age > 1d)"));
+    }
+
+    #[test]
+    fn parse_size_units() {
+        assert_eq!(parse_size("100"), Ok(100));
+        assert_eq!(parse_size("2MB"), Ok(2 * 1024 * 1024));
+        assert_eq!(parse_size("1 kb"), Ok(1024));
+        assert!(parse_size("2XB").is_err());
+    }
+
+    #[test]
+    fn parse_date_formats() {
+        let day = |days: u64| UNIX_EPOCH + Duration::from_secs(days * 86400);
+        assert_eq!(parse_date("1970-01-01"), Ok(day(0)));
+        assert_eq!(parse_date("2000-03-01"), Ok(day(11017)));
+        assert_eq!(
+            parse_date("2024-01-31T12:30"),
+            Ok(day(19753) + Duration::from_secs(12 * 3600 + 30 * 60))
+        );
+        assert!(parse_date("2024-13-01").is_err());
+        assert_eq!(parse_date("2024-02-29"), Ok(day(19782)));
+        assert!(parse_date("2023-02-29").is_err());
+        assert!(parse_date("2024-02-31").is_err());
+        assert!(parse_date("2024-04-31").is_err());
+        assert!(parse_date("30d").is_err());
+    }
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("90m"), Ok(Duration::from_secs(90 * 60)));
+        assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(14 * 24 * 60 * 60)));
+        assert!(parse_duration("30").is_err());
+    }
+}
@@ -0,0 +1,581 @@
+// mlcp - Music Library "Crud" Purge - Copyright (C) 2022, Ian Dunmore
+//
+// Free and open-source software, published under the MIT license; see
+// LICENSE file for more details.
+
+// Reads the artwork embedded in music files' tags, from:
+//
+//   ID3v2 (MP3, and anything else with a leading ID3 tag): APIC/PIC frames
+//   FLAC: PICTURE metadata blocks
+//   MP4 (M4A, AAC, ALAC): "covr" items
+//   Ogg (Vorbis, Opus): METADATA_BLOCK_PICTURE (and legacy COVERART) comments
+//
+// Only as much of each file as is needed to find the tags is read.  Files in
+// any other format are reported as having no embedded pictures.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+// The picture type of a front cover (as used by ID3 and FLAC).
+pub const FRONT_COVER: u32 = 3;
+
+// The largest tag (or MP4 "moov" box) we'll read into memory.
+const MAX_TAG_SIZE: u64 = 256 * 1024 * 1024;
+
+// An image embedded in a music file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Picture {
+    // The ID3/FLAC picture type (e.g. FRONT_COVER).
+    pub picture_type: u32,
+    pub data: Vec<u8>,
+}
+
+// Reads the pictures embedded in a music file, in the order they appear.
+pub fn read_pictures(path: &Path) -> io::Result<Vec<Picture>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut header = [0u8; 10];
+    let header_len = read_up_to(&mut reader, &mut header)?;
+    let header = &header[..header_len];
+
+    if header.starts_with(b"ID3") && header_len == 10 {
+        // An ID3 tag may also precede a FLAC stream.
+        let mut tag_size = syncsafe(&header[6..10]) as u64;
+        if header[5] & 0x10 != 0 { tag_size += 10; }
+        let body = read_exact_vec(&mut reader, tag_size)?;
+        let mut pictures = parse_id3(header[3], header[5], &body);
+
+        let mut magic = [0u8; 4];
+        if read_up_to(&mut reader, &mut magic)? == 4 && &magic == b"fLaC" {
+            pictures.extend(read_flac_pictures(&mut reader)?);
+        }
+        return Ok(pictures);
+    }
+
+    reader.seek(SeekFrom::Start(0))?;
+    if header.starts_with(b"fLaC") {
+        reader.seek(SeekFrom::Start(4))?;
+        return read_flac_pictures(&mut reader);
+    }
+    if header.starts_with(b"OggS") { return read_ogg_pictures(&mut reader); }
+    if header_len >= 8 && &header[4..8] == b"ftyp" { return read_mp4_pictures(&mut reader); }
+    Ok(Vec::new())
+}
+
+// The front cover from a file's pictures; or, as many taggers don't set the
+// picture type, the first picture if none is marked as the front cover.
+pub fn front_cover(pictures: Vec<Picture>) -> Option<Picture> {
+    let front = pictures.iter().position(|p| p.picture_type == FRONT_COVER).unwrap_or(0);
+    pictures.into_iter().nth(front)
+}
+
+// The file extension for an image, from the "magic" bytes at its start.
+pub fn image_extension(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\xff\xd8\xff") { return Some("jpg"); }
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") { return Some("png"); }
+    if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") { return Some("gif"); }
+    if data.starts_with(b"BM") { return Some("bmp"); }
+    if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") { return Some("webp"); }
+    None
+}
+
+// ID3v2 ...
+
+// Parses the APIC (PIC in v2.2) frames of an ID3v2 tag body.
+fn parse_id3(version: u8, flags: u8, body: &[u8]) -> Vec<Picture> {
+    let mut pictures = Vec::new();
+    if !(2..=4).contains(&version) { return pictures; }
+
+    // Before v2.4, unsynchronisation applies to the whole tag.
+    let unsynced;
+    let body = if flags & 0x80 != 0 && version < 4 {
+        unsynced = remove_unsync(body);
+        &unsynced[..]
+    } else {
+        body
+    };
+
+    // Skip any extended header; v2.3 doesn't count its own size field.
+    let mut pos = 0;
+    if flags & 0x40 != 0 && body.len() >= 4 {
+        pos = match version {
+            3 => be_u32(&body[0..4]) as usize + 4,
+            4 => syncsafe(&body[0..4]) as usize,
+            _ => 0,
+        };
+    }
+
+    let (id_len, header_len) = if version == 2 { (3, 6) } else { (4, 10) };
+    while pos + header_len <= body.len() {
+        let header = &body[pos..pos + header_len];
+        // Padding follows the last frame.
+        if header[0] == 0 { break; }
+        let size = match version {
+            2 => be_u24(&header[3..6]) as usize,
+            3 => be_u32(&header[4..8]) as usize,
+            _ => syncsafe(&header[4..8]) as usize,
+        };
+        let start = pos + header_len;
+        let end = start.saturating_add(size).min(body.len());
+        pos = start.saturating_add(size);
+
+        let id = &header[..id_len];
+        if id != b"APIC" && id != b"PIC" { continue; }
+
+        let format_flags = if version == 2 { 0 } else { header[9] };
+        let mut data = body[start..end].to_vec();
+        match version {
+            // Compressed or encrypted frames can't be read.
+            3 if format_flags & 0xc0 != 0 => continue,
+            4 if format_flags & 0x0c != 0 => continue,
+            4 => {
+                if format_flags & 0x02 != 0 { data = remove_unsync(&data); }
+                if format_flags & 0x01 != 0 { data = data.get(4..).unwrap_or_default().to_vec(); }
+            }
+            _ => {}
+        }
+        if let Some(picture) = parse_apic(version, &data) { pictures.push(picture); }
+    }
+    pictures
+}
+
+// Parses an APIC frame: encoding, MIME type (or v2.2 image format), picture
+// type, description and the image data.
+fn parse_apic(version: u8, data: &[u8]) -> Option<Picture> {
+    let encoding = *data.first()?;
+    let mut pos = 1;
+    if version == 2 {
+        pos += 3;
+    } else {
+        pos += data.get(pos..)?.iter().position(|b| *b == 0)? + 1;
+    }
+    let picture_type = *data.get(pos)? as u32;
+    pos += 1;
+
+    // UTF-16 descriptions end with a double null, on a character boundary.
+    let description = data.get(pos..)?;
+    pos += if encoding == 1 || encoding == 2 {
+        description.chunks(2).position(|c| c == [0, 0])? * 2 + 2
+    } else {
+        description.iter().position(|b| *b == 0)? + 1
+    };
+    Some(Picture { picture_type, data: data.get(pos..)?.to_vec() })
+}
+
+// Reverses ID3 unsynchronisation, where 0xFF 0x00 stands for 0xFF.
+fn remove_unsync(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    let mut previous = 0u8;
+    for &byte in data {
+        if !(previous == 0xff && byte == 0) { result.push(byte); }
+        previous = byte;
+    }
+    result
+}
+
+// FLAC ...
+
+// Reads the PICTURE blocks from FLAC metadata, which starts at the reader's
+// position (just after the "fLaC" marker).
+fn read_flac_pictures<R: Read + Seek>(reader: &mut R) -> io::Result<Vec<Picture>> {
+    let mut pictures = Vec::new();
+    loop {
+        let mut header = [0u8; 4];
+        if read_up_to(reader, &mut header)? < 4 { break; }
+        let block_type = header[0] & 0x7f;
+        let length = be_u24(&header[1..4]) as u64;
+        if block_type == 6 {
+            let block = read_exact_vec(reader, length)?;
+            if let Some(picture) = parse_flac_picture(&block) { pictures.push(picture); }
+        } else {
+            reader.seek(SeekFrom::Current(length as i64))?;
+        }
+        // The high bit marks the last metadata block.
+        if header[0] & 0x80 != 0 { break; }
+    }
+    Ok(pictures)
+}
+
+// Parses a FLAC PICTURE block (also used by Ogg's METADATA_BLOCK_PICTURE):
+// type, MIME type, description, dimensions, and the image data.
+fn parse_flac_picture(block: &[u8]) -> Option<Picture> {
+    let field = |pos: usize| block.get(pos..pos + 4).map(be_u32);
+    let picture_type = field(0)?;
+    let mut pos = 8 + field(4)? as usize;
+    pos += 4 + field(pos)? as usize;
+    // Width, height, colour depth and the number of colours.
+    pos += 16;
+    let length = field(pos)? as usize;
+    pos += 4;
+    Some(Picture { picture_type, data: block.get(pos..pos.checked_add(length)?)?.to_vec() })
+}
+
+// MP4 ...
+
+// Reads the "covr" items from the iTunes metadata in an MP4 file's "moov" box.
+fn read_mp4_pictures<R: Read + Seek>(reader: &mut R) -> io::Result<Vec<Picture>> {
+    loop {
+        let mut header = [0u8; 8];
+        if read_up_to(reader, &mut header)? < 8 { return Ok(Vec::new()); }
+        let mut size = be_u32(&header[0..4]) as u64;
+        let mut header_len = 8;
+        if size == 1 {
+            let mut large_size = [0u8; 8];
+            reader.read_exact(&mut large_size)?;
+            size = u64::from_be_bytes(large_size);
+            header_len = 16;
+        }
+
+        if &header[4..8] == b"moov" {
+            let moov = if size == 0 {
+                let mut moov = Vec::new();
+                reader.take(MAX_TAG_SIZE).read_to_end(&mut moov)?;
+                moov
+            } else {
+                read_exact_vec(reader, size.saturating_sub(header_len))?
+            };
+            return Ok(parse_moov(&moov));
+        }
+        // A size of zero runs to the end of the file; a corrupt size, that
+        // doesn't move us forward through it, ends the search.
+        if size == 0 || size < header_len { return Ok(Vec::new()); }
+        let Ok(skip) = i64::try_from(size - header_len) else { return Ok(Vec::new()) };
+        let position = reader.stream_position()?;
+        if reader.seek(SeekFrom::Current(skip))? < position { return Ok(Vec::new()); }
+    }
+}
+
+// Finds the cover art in a "moov" box, at moov/udta/meta/ilst/covr/data.
+fn parse_moov(moov: &[u8]) -> Vec<Picture> {
+    let meta = mp4_child(moov, b"udta").and_then(|udta| mp4_child(udta, b"meta"))
+        .or_else(|| mp4_child(moov, b"meta"));
+    // "meta" is a full box, with 4 bytes of version and flags.
+    let covr = meta.and_then(|meta| meta.get(4..))
+        .and_then(|meta| mp4_child(meta, b"ilst"))
+        .and_then(|ilst| mp4_child(ilst, b"covr"));
+
+    // Each "data" box has a type indicator and locale ahead of the image.
+    // MP4 doesn't record the picture type, so all covers are front covers.
+    mp4_children(covr.unwrap_or_default())
+        .filter(|(box_type, _)| box_type == b"data")
+        .filter_map(|(_, data)| data.get(8..))
+        .map(|data| Picture { picture_type: FRONT_COVER, data: data.to_vec() })
+        .collect()
+}
+
+// The body of the first child box of the given type.
+fn mp4_child<'a>(data: &'a [u8], box_type: &[u8; 4]) -> Option<&'a [u8]> {
+    mp4_children(data).find(|(t, _)| t == box_type).map(|(_, body)| body)
+}
+
+// Iterates over the (type, body) of the boxes in a box's body.
+fn mp4_children(data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    let mut pos = 0;
+    std::iter::from_fn(move || {
+        let header = data.get(pos..pos + 8)?;
+        let size = match be_u32(&header[0..4]) as usize {
+            0 => data.len() - pos,
+            size if size < 8 => return None,
+            size => size,
+        };
+        let body = data.get(pos + 8..pos.checked_add(size)?)?;
+        pos += size;
+        Some((header[4..8].try_into().ok()?, body))
+    })
+}
+
+// Ogg ...
+
+// Reads the pictures from the comment header (the second packet) of an Ogg
+// Vorbis or Opus stream.
+fn read_ogg_pictures<R: Read>(reader: &mut R) -> io::Result<Vec<Picture>> {
+    let mut packets: Vec<Vec<u8>> = Vec::new();
+    let mut packet = Vec::new();
+    while packets.len() < 2 {
+        let mut header = [0u8; 27];
+        if read_up_to(reader, &mut header)? < 27 || &header[0..4] != b"OggS" { break; }
+        let mut segments = vec![0u8; header[26] as usize];
+        reader.read_exact(&mut segments)?;
+
+        // Packets span segments (and pages) until a segment under 255 bytes.
+        for length in segments {
+            packet.extend(read_exact_vec(reader, length as u64)?);
+            if length < 255 { packets.push(std::mem::take(&mut packet)); }
+        }
+        if packet.len() as u64 > MAX_TAG_SIZE { break; }
+    }
+
+    Ok(packets.get(1).map(|comments| parse_ogg_comments(comments)).unwrap_or_default())
+}
+
+// Parses the pictures from a Vorbis comment header; the comments are
+// "KEY=value" pairs, with pictures base64-encoded in the value.
+fn parse_ogg_comments(packet: &[u8]) -> Vec<Picture> {
+    let mut pictures = Vec::new();
+    let comments = if packet.starts_with(b"\x03vorbis") {
+        &packet[7..]
+    } else if packet.starts_with(b"OpusTags") {
+        &packet[8..]
+    } else {
+        return pictures;
+    };
+
+    let field = |pos: usize| comments.get(pos..pos + 4).map(le_u32);
+    let Some(vendor_length) = field(0) else { return pictures };
+    let mut pos = 4 + vendor_length as usize;
+    let Some(count) = field(pos) else { return pictures };
+    pos += 4;
+    for _ in 0..count {
+        let Some(length) = field(pos) else { break };
+        let Some(comment) = comments.get(pos + 4..pos + 4 + length as usize) else { break };
+        pos += 4 + length as usize;
+
+        let Some(split) = comment.iter().position(|b| *b == b'=') else { continue };
+        let (key, value) = (&comment[..split], &comment[split + 1..]);
+        if key.eq_ignore_ascii_case(b"METADATA_BLOCK_PICTURE") {
+            if let Some(picture) = decode_base64(value).as_deref().and_then(parse_flac_picture) {
+                pictures.push(picture);
+            }
+        } else if key.eq_ignore_ascii_case(b"COVERART") {
+            if let Some(data) = decode_base64(value) {
+                pictures.push(Picture { picture_type: FRONT_COVER, data });
+            }
+        }
+    }
+    pictures
+}
+
+// Decodes standard base64, ignoring padding and whitespace.
+fn decode_base64(text: &[u8]) -> Option<Vec<u8>> {
+    let mut result = Vec::with_capacity(text.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for &c in text {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' | b'\r' | b'\n' | b' ' => continue,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((buffer >> bits) as u8);
+        }
+    }
+    Some(result)
+}
+
+// Helpers ...
+
+// Reads into the buffer until it is full or the input ends; returns the
+// number of bytes read.
+fn read_up_to<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut total = 0;
+    while total < buffer.len() {
+        match reader.read(&mut buffer[total..])? {
+            0 => break,
+            n => total += n,
+        }
+    }
+    Ok(total)
+}
+
+// Reads exactly "length" bytes, refusing (as corrupt) anything implausibly large.
+fn read_exact_vec<R: Read>(reader: &mut R, length: u64) -> io::Result<Vec<u8>> {
+    if length > MAX_TAG_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "tag too large"));
+    }
+    let mut data = vec![0u8; length as usize];
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
+
+// A 28-bit ID3 "syncsafe" integer, with 7 bits in each byte.
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |n, b| (n << 7) | (*b & 0x7f) as u32)
+}
+
+fn be_u24(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |n, b| (n << 8) | *b as u32)
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn le_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const IMAGE: &[u8] = b"\xff\xd8\xff\xe0 jpeg data";
+
+    // A FLAC PICTURE block, for the test image.
+    fn flac_picture(picture_type: u32) -> Vec<u8> {
+        let mut block = picture_type.to_be_bytes().to_vec();
+        for text in [&b"image/jpeg"[..], b"Cover"] {
+            block.extend((text.len() as u32).to_be_bytes());
+            block.extend(text);
+        }
+        block.extend([0u8; 16]);
+        block.extend((IMAGE.len() as u32).to_be_bytes());
+        block.extend(IMAGE);
+        block
+    }
+
+    // An MP4 box, from its type and body.
+    fn mp4_box(box_type: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut result = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        result.extend(box_type);
+        result.extend(body);
+        result
+    }
+
+    fn syncsafe_bytes(n: u32) -> [u8; 4] {
+        [(n >> 21) as u8 & 0x7f, (n >> 14) as u8 & 0x7f, (n >> 7) as u8 & 0x7f, n as u8 & 0x7f]
+    }
+
+    #[test]
+    fn id3v23_apic() {
+        // A UTF-16 description, then a back cover.
+        let mut frame = b"\x01image/jpeg\0\x03\xff\xfeC\0\0\0".to_vec();
+        frame.extend(IMAGE);
+        let mut body = b"APIC".to_vec();
+        body.extend((frame.len() as u32).to_be_bytes());
+        body.extend([0, 0]);
+        body.extend(&frame);
+        body.extend([0u8; 20]);
+
+        let pictures = parse_id3(3, 0, &body);
+        assert_eq!(pictures, vec![Picture { picture_type: FRONT_COVER, data: IMAGE.to_vec() }]);
+    }
+
+    #[test]
+    fn id3v24_unsynchronised_apic() {
+        // The frame is unsynchronised, so the 0xFF bytes are followed by 0x00.
+        let mut frame = b"\0image/jpeg\0\x04back\0".to_vec();
+        frame.extend(IMAGE);
+        let unsynced: Vec<u8> = frame.iter()
+            .flat_map(|b| if *b == 0xff { vec![0xff, 0] } else { vec![*b] })
+            .collect();
+        let mut body = b"APIC".to_vec();
+        body.extend(syncsafe_bytes(unsynced.len() as u32));
+        body.extend([0, 0x02]);
+        body.extend(&unsynced);
+
+        let pictures = parse_id3(4, 0, &body);
+        assert_eq!(pictures, vec![Picture { picture_type: 4, data: IMAGE.to_vec() }]);
+    }
+
+    #[test]
+    fn id3v22_pic() {
+        let mut frame = b"\0JPG\x03\0".to_vec();
+        frame.extend(IMAGE);
+        let mut body = b"PIC".to_vec();
+        body.extend(&(frame.len() as u32).to_be_bytes()[1..]);
+        body.extend(&frame);
+        assert_eq!(parse_id3(2, 0, &body)[0].data, IMAGE);
+    }
+
+    #[test]
+    fn flac_picture_blocks() {
+        // STREAMINFO, then the last block; a PICTURE.
+        let mut flac = vec![0, 0, 0, 34];
+        flac.extend([0u8; 34]);
+        let picture = flac_picture(FRONT_COVER);
+        flac.push(0x86);
+        flac.extend(&(picture.len() as u32).to_be_bytes()[1..]);
+        flac.extend(&picture);
+
+        let pictures = read_flac_pictures(&mut Cursor::new(flac)).unwrap();
+        assert_eq!(pictures, vec![Picture { picture_type: FRONT_COVER, data: IMAGE.to_vec() }]);
+    }
+
+    #[test]
+    fn mp4_covr() {
+        let mut data = vec![0, 0, 0, 13, 0, 0, 0, 0];
+        data.extend(IMAGE);
+        let ilst = mp4_box(b"ilst", &mp4_box(b"covr", &mp4_box(b"data", &data)));
+        let mut meta = vec![0u8; 4];
+        meta.extend(ilst);
+        let moov = mp4_box(b"moov", &mp4_box(b"udta", &mp4_box(b"meta", &meta)));
+        let mut mp4 = mp4_box(b"ftyp", b"M4A ");
+        mp4.extend(mp4_box(b"mdat", b"audio"));
+        mp4.extend(moov);
+
+        let pictures = read_mp4_pictures(&mut Cursor::new(mp4)).unwrap();
+        assert_eq!(pictures, vec![Picture { picture_type: FRONT_COVER, data: IMAGE.to_vec() }]);
+    }
+
+    #[test]
+    fn ogg_metadata_block_picture() {
+        // flac_picture(FRONT_COVER), base64-encoded.
+        let encoded =
+            b"AAAAAwAAAAppbWFnZS9qcGVnAAAABUNvdmVyAAAAAAAAAAAAAAAAAAAAAAAAAA7/2P/gIGpwZWcgZGF0YQ==";
+        assert_eq!(decode_base64(encoded).unwrap(), flac_picture(FRONT_COVER));
+
+        let mut comment = b"METADATA_BLOCK_PICTURE=".to_vec();
+        comment.extend(encoded);
+        let mut packet = b"\x03vorbis".to_vec();
+        packet.extend(4u32.to_le_bytes());
+        packet.extend(b"mlcp");
+        packet.extend(2u32.to_le_bytes());
+        for text in [&b"TITLE=Song"[..], &comment] {
+            packet.extend((text.len() as u32).to_le_bytes());
+            packet.extend(text);
+        }
+
+        // Two pages; the identification packet, and the comment packet.
+        let mut ogg = Vec::new();
+        for body in [&b"\x01vorbis"[..], &packet] {
+            ogg.extend(b"OggS\0\0");
+            ogg.extend([0u8; 20]);
+            ogg.push(1);
+            ogg.push(body.len() as u8);
+            ogg.extend(body);
+        }
+        let pictures = read_ogg_pictures(&mut Cursor::new(ogg)).unwrap();
+        assert_eq!(pictures, vec![Picture { picture_type: FRONT_COVER, data: IMAGE.to_vec() }]);
+    }
+
+    #[test]
+    fn front_cover_by_type() {
+        let picture = |picture_type, data: &[u8]| Picture { picture_type, data: data.to_vec() };
+        let back = picture(4, b"back");
+        let front = picture(FRONT_COVER, b"front");
+        assert_eq!(front_cover(vec![back.clone(), front.clone()]), Some(front));
+        assert_eq!(front_cover(vec![back.clone()]), Some(back));
+        assert_eq!(front_cover(Vec::new()), None);
+    }
+
+    #[test]
+    fn image_extension_from_magic() {
+        assert_eq!(image_extension(IMAGE), Some("jpg"));
+        assert_eq!(image_extension(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR"), Some("png"));
+        assert_eq!(image_extension(b"RIFF\0\0\0\0WEBPVP8 "), Some("webp"));
+        assert_eq!(image_extension(b"jpeg"), None);
+    }
+
+    #[test]
+    fn truncated_tags_are_ignored() {
+        assert!(parse_id3(3, 0, b"APIC\0\0\0\x20\0\0\0").is_empty());
+        assert!(parse_flac_picture(&flac_picture(FRONT_COVER)[..40]).is_none());
+        assert!(parse_moov(&mp4_box(b"udta", b"\0\0\0\x20meta")).is_empty());
+        assert!(decode_base64(b"not base64!").is_none());
+
+        // A box whose 64-bit size overflows a seek doesn't loop forever.
+        let mut mp4 = mp4_box(b"ftyp", b"M4A \0\0\0\0");
+        mp4.extend(b"\0\0\0\x01free\xff\xff\xff\xff\xff\xff\xff\xf0");
+        assert_eq!(mp4.len(), 32);
+        assert!(read_mp4_pictures(&mut Cursor::new(mp4)).unwrap().is_empty());
+    }
+}